@@ -0,0 +1,472 @@
+use crate::input::{InvalidRecord, RecordInput};
+use crate::scale::{InputScale, OutputScale};
+use crate::{AccountSummary, TxRecord};
+use ::csv::{Reader, ReaderBuilder, StringRecord, Trim, Writer};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+/// column order of the files without header line, when no other order is given
+pub const DEFAULT_COLUMNS: [&str; 4] = ["type", "client", "tx", "amount"];
+
+// columns without which no record can be decoded
+const REQUIRED_COLUMNS: [&str; 3] = ["type", "client", "tx"];
+
+/// Layout of the CSV input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// the first line names the columns, it is skipped when [`CsvOptions::columns`] is set
+    pub has_headers: bool,
+    /// column names in file order, e.g. `tx, client, type, amount`, unknown names are ignored
+    ///
+    /// defaults to the header line, or to [`DEFAULT_COLUMNS`] without header line
+    pub columns: Option<Vec<String>>,
+    pub delimiter: u8,
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions {
+            has_headers: true,
+            columns: None,
+            delimiter: b',',
+        }
+    }
+}
+
+impl CsvOptions {
+    /// checks that the given columns include `type`, `client` and `tx`
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(columns) = &self.columns {
+            for required in REQUIRED_COLUMNS {
+                if !columns.iter().any(|column| column == required) {
+                    return Err(format!("missing column: {}", required));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Streaming reader decoding `type, client, tx, amount` CSV lines into [`TxRecord`]s
+///
+/// lines that can not be decoded are yielded as errors, the following lines are still read
+pub struct TxRecordReader<R: Read> {
+    reader: Reader<ReadBytes<R>>,
+    headers: Option<StringRecord>,
+    record: StringRecord,
+    line: Option<u64>,
+    // bytes of the last record in the buffer of the reader
+    raw: Range<usize>,
+    scale: Option<InputScale>,
+    admin_records: bool,
+}
+
+impl<R: Read> TxRecordReader<R> {
+    pub fn new(reader: R) -> TxRecordReader<R> {
+        TxRecordReader::with_options(reader, &CsvOptions::default())
+    }
+
+    pub fn with_options(reader: R, options: &CsvOptions) -> TxRecordReader<R> {
+        // the header line, if any, is read on the first call to next
+        let headers = match (&options.columns, options.has_headers) {
+            (Some(columns), _) => Some(StringRecord::from(columns.clone())),
+            (None, false) => Some(StringRecord::from(DEFAULT_COLUMNS.to_vec())),
+            (None, true) => None,
+        };
+
+        // big files are not a problem because
+        // the csv crate uses a BufReader of size 8 * (1 << 10) bytes = 8 KiB
+        TxRecordReader {
+            reader: ReaderBuilder::new()
+                .trim(Trim::All)
+                .has_headers(options.has_headers)
+                .delimiter(options.delimiter)
+                .from_reader(ReadBytes::new(reader)),
+            headers,
+            record: StringRecord::new(),
+            line: None,
+            raw: 0..0,
+            scale: None,
+            admin_records: true,
+        }
+    }
+
+    /// rounds or rejects the amounts with more decimal places than the scale
+    pub fn with_scale(mut self, scale: InputScale) -> TxRecordReader<R> {
+        self.scale = Some(scale);
+        self
+    }
+
+    /// yields the admin records, such as `unlock`, as errors, e.g. for end-user inputs
+    pub fn without_admin_records(mut self) -> TxRecordReader<R> {
+        self.admin_records = false;
+        self
+    }
+
+    /// line number, starting at 1, of the last record yielded by the iterator
+    pub fn line(&self) -> Option<u64> {
+        self.line
+    }
+
+    /// last record yielded by the iterator, as read from the input, trimmed
+    pub fn raw_record(&self) -> String {
+        let bytes = &self.reader.get_ref().buffer[self.raw.clone()];
+        String::from_utf8_lossy(bytes).trim().to_string()
+    }
+
+    // keeps the bytes from `start` to the current position of the reader as the raw record
+    fn keep_raw(&mut self, start: Option<u64>) {
+        let end = self.reader.position().byte();
+        let read_bytes = self.reader.get_ref();
+        let start = start.unwrap_or(end).max(read_bytes.offset);
+        self.raw = (start - read_bytes.offset) as usize..(end - read_bytes.offset) as usize;
+    }
+
+    fn decode_record(&self) -> Result<TxRecord, CsvLineErrorKind> {
+        let csv_record: RecordInput = self
+            .record
+            .deserialize(self.headers.as_ref())
+            .map_err(CsvLineErrorKind::Csv)?;
+
+        csv_record
+            .decode(self.scale.as_ref(), self.admin_records)
+            .map_err(|e| match e {
+                InvalidRecord::UnknownType(t) => CsvLineErrorKind::UnknownType(t),
+                InvalidRecord::MissingAmount => CsvLineErrorKind::MissingAmount,
+                InvalidRecord::MissingDestination => CsvLineErrorKind::MissingDestination,
+                InvalidRecord::MissingReason => CsvLineErrorKind::MissingReason,
+                InvalidRecord::InvalidBucket(bucket) => CsvLineErrorKind::InvalidBucket(bucket),
+                InvalidRecord::AdminRecord(t) => CsvLineErrorKind::AdminRecord(t),
+                InvalidRecord::TooManyDecimalPlaces(places) => {
+                    CsvLineErrorKind::TooManyDecimalPlaces(places)
+                }
+                InvalidRecord::InvalidCurrency(code) => CsvLineErrorKind::InvalidCurrency(code),
+            })
+    }
+}
+
+impl<R: Read> Iterator for TxRecordReader<R> {
+    type Item = Result<TxRecord, CsvLineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.headers.is_none() {
+            match self.reader.headers() {
+                Ok(headers) => self.headers = Some(headers.clone()),
+                Err(e) => return Some(Err(CsvLineError::from_csv(e))),
+            }
+        }
+
+        // the bytes of the previous records are no longer needed
+        let position = self.reader.position().byte();
+        self.reader.get_mut().discard_before(position);
+
+        let read = self.reader.read_record(&mut self.record);
+        self.line = self.record.position().map(|p| p.line());
+
+        match read {
+            Ok(false) => None,
+            Ok(true) => {
+                self.keep_raw(self.record.position().map(|p| p.byte()));
+                Some(self.decode_record().map_err(|kind| CsvLineError {
+                    line: self.line,
+                    kind,
+                }))
+            }
+            Err(e) => {
+                self.keep_raw(e.position().map(|p| p.byte()));
+                let error = CsvLineError::from_csv(e);
+                self.line = error.line;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+// keeps the bytes read from the input until the records they contain have been yielded
+struct ReadBytes<R> {
+    inner: R,
+    buffer: Vec<u8>,
+    // position in the input of the first byte of the buffer
+    offset: u64,
+}
+
+impl<R: Read> ReadBytes<R> {
+    fn new(inner: R) -> ReadBytes<R> {
+        ReadBytes {
+            inner,
+            buffer: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    fn discard_before(&mut self, position: u64) {
+        let count = (position.saturating_sub(self.offset) as usize).min(self.buffer.len());
+        self.buffer.drain(..count);
+        self.offset += count as u64;
+    }
+}
+
+impl<R: Read> Read for ReadBytes<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.buffer.extend_from_slice(&buf[..count]);
+        Ok(count)
+    }
+}
+
+/// A CSV line that could not be decoded into a [`TxRecord`]
+#[derive(Debug)]
+pub struct CsvLineError {
+    line: Option<u64>,
+    kind: CsvLineErrorKind,
+}
+
+#[derive(Debug)]
+pub enum CsvLineErrorKind {
+    /// malformed line: wrong field count, unparsable id or amount, ..
+    Csv(::csv::Error),
+    /// the `type` column is not one of the supported transaction types
+    UnknownType(String),
+    /// empty `amount` column on a deposit, a withdrawal or a transfer
+    MissingAmount,
+    /// empty `destination` column on a transfer
+    MissingDestination,
+    /// empty `reason` column on an admin record
+    MissingReason,
+    /// the `bucket` column of an adjustment is neither `available` nor `held`
+    InvalidBucket(String),
+    /// admin record, such as `unlock`, read by a reader without admin records
+    AdminRecord(String),
+    /// the amount has more decimal places than the [`InputScale`], which does not round
+    TooManyDecimalPlaces(u32),
+    /// the `currency` column of a deposit or a withdrawal is not a three-letter code
+    InvalidCurrency(String),
+}
+
+impl CsvLineError {
+    fn from_csv(e: ::csv::Error) -> CsvLineError {
+        CsvLineError {
+            line: e.position().map(|p| p.line()),
+            kind: CsvLineErrorKind::Csv(e),
+        }
+    }
+
+    /// line number in the input, starting at 1
+    pub fn line(&self) -> Option<u64> {
+        self.line
+    }
+
+    pub fn kind(&self) -> &CsvLineErrorKind {
+        &self.kind
+    }
+
+    /// stable, machine-readable identifier of the error kind
+    pub fn code(&self) -> &'static str {
+        match self.kind {
+            CsvLineErrorKind::Csv(_) => "malformed_line",
+            CsvLineErrorKind::UnknownType(_) => "unknown_type",
+            CsvLineErrorKind::MissingAmount => "missing_amount",
+            CsvLineErrorKind::MissingDestination => "missing_destination",
+            CsvLineErrorKind::MissingReason => "missing_reason",
+            CsvLineErrorKind::InvalidBucket(_) => "invalid_bucket",
+            CsvLineErrorKind::AdminRecord(_) => "admin_record",
+            CsvLineErrorKind::TooManyDecimalPlaces(_) => "too_many_decimal_places",
+            CsvLineErrorKind::InvalidCurrency(_) => "invalid_currency",
+        }
+    }
+}
+
+impl fmt::Display for CsvLineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            CsvLineErrorKind::Csv(e) => write!(f, "csv error: {}", e),
+            CsvLineErrorKind::UnknownType(t) => match self.line {
+                Some(line) => write!(f, "unknown type on line {}: {}", line, t),
+                None => write!(f, "unknown type: {}", t),
+            },
+            CsvLineErrorKind::MissingAmount => match self.line {
+                Some(line) => write!(f, "missing amount on line {}", line),
+                None => write!(f, "missing amount"),
+            },
+            CsvLineErrorKind::MissingDestination => match self.line {
+                Some(line) => write!(f, "missing destination on line {}", line),
+                None => write!(f, "missing destination"),
+            },
+            CsvLineErrorKind::MissingReason => match self.line {
+                Some(line) => write!(f, "missing reason on line {}", line),
+                None => write!(f, "missing reason"),
+            },
+            CsvLineErrorKind::InvalidBucket(bucket) => match self.line {
+                Some(line) => write!(f, "invalid bucket on line {}: {}", line, bucket),
+                None => write!(f, "invalid bucket: {}", bucket),
+            },
+            CsvLineErrorKind::AdminRecord(t) => match self.line {
+                Some(line) => write!(f, "admin record not accepted on line {}: {}", line, t),
+                None => write!(f, "admin record not accepted: {}", t),
+            },
+            CsvLineErrorKind::TooManyDecimalPlaces(places) => match self.line {
+                Some(line) => write!(
+                    f,
+                    "amount with more than {} decimal places on line {}",
+                    places, line
+                ),
+                None => write!(f, "amount with more than {} decimal places", places),
+            },
+            CsvLineErrorKind::InvalidCurrency(code) => match self.line {
+                Some(line) => write!(f, "invalid currency on line {}: {}", line, code),
+                None => write!(f, "invalid currency: {}", code),
+            },
+        }
+    }
+}
+
+impl Error for CsvLineError {}
+
+/// Writer encoding account summaries as `client, available, held, total, locked` CSV lines
+///
+/// the header line is written with the first summary
+pub struct AccountSummaryWriter<W: Write> {
+    writer: Writer<W>,
+    scale: Option<OutputScale>,
+    currency: bool,
+    credit_limit: bool,
+    negative: bool,
+    adjustments: bool,
+    fees: bool,
+    authorizations: bool,
+    count: usize,
+}
+
+impl<W: Write> AccountSummaryWriter<W> {
+    pub fn new(writer: W) -> AccountSummaryWriter<W> {
+        AccountSummaryWriter {
+            writer: Writer::from_writer(writer),
+            scale: None,
+            currency: false,
+            credit_limit: false,
+            negative: false,
+            adjustments: false,
+            fees: false,
+            authorizations: false,
+            count: 0,
+        }
+    }
+
+    /// writes the amounts with exactly the decimal places of the scale
+    pub fn with_scale(mut self, scale: OutputScale) -> AccountSummaryWriter<W> {
+        self.scale = Some(scale);
+        self
+    }
+
+    /// adds a `currency` column after the client, empty for the default currency
+    pub fn with_currency(mut self) -> AccountSummaryWriter<W> {
+        self.currency = true;
+        self
+    }
+
+    /// adds `credit_limit` and `headroom` columns after `locked`
+    pub fn with_credit_limit(mut self) -> AccountSummaryWriter<W> {
+        self.credit_limit = true;
+        self
+    }
+
+    /// adds `shortfall` and `negative` columns at the end, see [`AccountSummary::is_negative`]
+    pub fn with_negative_flag(mut self) -> AccountSummaryWriter<W> {
+        self.negative = true;
+        self
+    }
+
+    /// adds `adjustments` and `adjusted` columns at the end, counting and summing the adjustments
+    pub fn with_adjustments(mut self) -> AccountSummaryWriter<W> {
+        self.adjustments = true;
+        self
+    }
+
+    /// adds a `fees` column at the end, the total of the fees charged to the account
+    pub fn with_fees(mut self) -> AccountSummaryWriter<W> {
+        self.fees = true;
+        self
+    }
+
+    /// adds an `authorized` column at the end, the part of `held` reserved by authorizations
+    pub fn with_authorizations(mut self) -> AccountSummaryWriter<W> {
+        self.authorizations = true;
+        self
+    }
+
+    pub fn write(&mut self, client_id: u16, summary: &AccountSummary) -> ::csv::Result<()> {
+        if self.count == 0 {
+            let mut headers = vec!["client"];
+            if self.currency {
+                headers.push("currency");
+            }
+            headers.extend(["available", "held", "total", "locked"]);
+            if self.credit_limit {
+                headers.extend(["credit_limit", "headroom"]);
+            }
+            if self.negative {
+                headers.extend(["shortfall", "negative"]);
+            }
+            if self.adjustments {
+                headers.extend(["adjustments", "adjusted"]);
+            }
+            if self.fees {
+                headers.push("fees");
+            }
+            if self.authorizations {
+                headers.push("authorized");
+            }
+            self.writer.write_record(headers)?;
+        }
+
+        let scaled = |amount| match &self.scale {
+            Some(scale) => scale.apply(amount),
+            None => amount,
+        };
+
+        let mut fields = vec![client_id.to_string()];
+        if self.currency {
+            fields.push(summary.currency().to_string());
+        }
+        fields.extend([
+            scaled(summary.available_amount()).to_string(),
+            scaled(summary.held_amount()).to_string(),
+            scaled(summary.total_amount()).to_string(),
+            summary.is_locked().to_string(),
+        ]);
+        if self.credit_limit {
+            fields.extend([
+                scaled(summary.credit_limit()).to_string(),
+                scaled(summary.headroom()).to_string(),
+            ]);
+        }
+        if self.negative {
+            fields.extend([
+                scaled(summary.shortfall()).to_string(),
+                summary.is_negative().to_string(),
+            ]);
+        }
+        if self.adjustments {
+            fields.extend([
+                summary.adjustments().to_string(),
+                scaled(summary.adjusted_amount()).to_string(),
+            ]);
+        }
+        if self.fees {
+            fields.push(scaled(summary.fees()).to_string());
+        }
+        if self.authorizations {
+            fields.push(scaled(summary.authorized_amount()).to_string());
+        }
+        self.writer.write_record(fields)?;
+
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,142 @@
+use crate::scale::InputScale;
+use crate::{Bucket, Currency, TxRecord, TxRecordMetadata};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::fmt;
+
+// input record shared by the CSV and JSON lines readers, so that both apply the same validation
+#[derive(Deserialize)]
+pub(crate) struct RecordInput {
+    r#type: String,
+    client: u16,
+    tx: u32,
+    // required by deposits and withdrawals, discarded for the other types
+    amount: Option<Decimal>,
+    // optional column, the default currency if missing or empty, discarded like the amount
+    #[serde(default)]
+    currency: Option<String>,
+    // optional column, required by transfers, discarded for the other types
+    #[serde(default)]
+    destination: Option<u16>,
+    // optional column, required by admin records, discarded for the other types
+    #[serde(default)]
+    reason: Option<String>,
+    // optional column, `available` if missing or empty, discarded for the other types than adjustments
+    #[serde(default)]
+    bucket: Option<String>,
+}
+
+/// Reason a decoded input record is not a valid [`TxRecord`]
+///
+/// negative amounts are rejected by [`crate::TxProc`] itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidRecord {
+    /// the `type` is not one of the supported transaction types
+    UnknownType(String),
+    /// a deposit, a withdrawal, a transfer, an authorization or an adjustment without amount
+    MissingAmount,
+    /// a transfer without destination client
+    MissingDestination,
+    /// an admin record without reason
+    MissingReason,
+    /// the bucket of an adjustment is neither `available` nor `held`
+    InvalidBucket(String),
+    /// an admin record, such as `unlock`, in an input that does not accept them
+    AdminRecord(String),
+    /// the amount has more decimal places than the [`InputScale`], which does not round
+    TooManyDecimalPlaces(u32),
+    /// the currency of a deposit, a withdrawal or a transfer is not a three-letter code
+    InvalidCurrency(String),
+}
+
+impl InvalidRecord {
+    /// stable, machine-readable identifier of the reason
+    pub fn code(&self) -> &'static str {
+        match self {
+            InvalidRecord::UnknownType(_) => "unknown_type",
+            InvalidRecord::MissingAmount => "missing_amount",
+            InvalidRecord::MissingDestination => "missing_destination",
+            InvalidRecord::MissingReason => "missing_reason",
+            InvalidRecord::InvalidBucket(_) => "invalid_bucket",
+            InvalidRecord::AdminRecord(_) => "admin_record",
+            InvalidRecord::TooManyDecimalPlaces(_) => "too_many_decimal_places",
+            InvalidRecord::InvalidCurrency(_) => "invalid_currency",
+        }
+    }
+}
+
+impl fmt::Display for InvalidRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidRecord::UnknownType(t) => write!(f, "unknown type: {}", t),
+            InvalidRecord::MissingAmount => write!(f, "amount is missing"),
+            InvalidRecord::MissingDestination => write!(f, "destination is missing"),
+            InvalidRecord::MissingReason => write!(f, "reason is missing"),
+            InvalidRecord::InvalidBucket(bucket) => write!(f, "invalid bucket: {}", bucket),
+            InvalidRecord::AdminRecord(t) => write!(f, "admin record not accepted: {}", t),
+            InvalidRecord::TooManyDecimalPlaces(places) => {
+                write!(f, "amount has more than {} decimal places", places)
+            }
+            InvalidRecord::InvalidCurrency(code) => write!(f, "invalid currency: {}", code),
+        }
+    }
+}
+
+impl RecordInput {
+    // admin records are only decoded with `admin_records`
+    pub(crate) fn decode(
+        self,
+        scale: Option<&InputScale>,
+        admin_records: bool,
+    ) -> Result<TxRecord, InvalidRecord> {
+        let metadata = TxRecordMetadata::new(self.client, self.tx);
+        let currency = match self.currency {
+            Some(code) => Currency::new(&code).ok_or(InvalidRecord::InvalidCurrency(code)),
+            None => Ok(Currency::default()),
+        };
+        let amount =
+            self.amount
+                .ok_or(InvalidRecord::MissingAmount)
+                .and_then(|amount| match scale {
+                    Some(scale) => scale
+                        .apply(amount)
+                        .ok_or(InvalidRecord::TooManyDecimalPlaces(scale.places)),
+                    None => Ok(amount),
+                });
+        let reason = self
+            .reason
+            .filter(|reason| !reason.trim().is_empty())
+            .ok_or(InvalidRecord::MissingReason);
+        let bucket = match self.bucket {
+            Some(bucket) if !bucket.trim().is_empty() => bucket
+                .trim()
+                .parse()
+                .map_err(|_| InvalidRecord::InvalidBucket(bucket)),
+            _ => Ok(Bucket::Available),
+        };
+
+        Ok(match self.r#type.as_str() {
+            "deposit" => TxRecord::DEPOSIT(metadata.with_currency(currency?), amount?),
+            "withdrawal" => TxRecord::WITHDRAWAL(metadata.with_currency(currency?), amount?),
+            "transfer" => TxRecord::TRANSFER(
+                metadata.with_currency(currency?),
+                self.destination.ok_or(InvalidRecord::MissingDestination)?,
+                amount?,
+            ),
+            "dispute" => TxRecord::DISPUTE(metadata),
+            "resolve" => TxRecord::RESOLVE(metadata),
+            "chargeback" => TxRecord::CHARGEBACK(metadata),
+            "authorize" => TxRecord::AUTHORIZE(metadata.with_currency(currency?), amount?),
+            "capture" => TxRecord::CAPTURE(metadata),
+            "void" => TxRecord::VOID(metadata),
+            "unlock" | "adjustment" if !admin_records => {
+                return Err(InvalidRecord::AdminRecord(self.r#type))
+            }
+            "unlock" => TxRecord::UNLOCK(metadata.with_currency(currency?), reason?),
+            "adjustment" => {
+                TxRecord::ADJUSTMENT(metadata.with_currency(currency?), bucket?, amount?, reason?)
+            }
+            _ => return Err(InvalidRecord::UnknownType(self.r#type)),
+        })
+    }
+}
@@ -0,0 +1,648 @@
+use crate::store::{InMemoryStore, TransactionStore};
+use crate::{
+    new_account, AccountKey, AccountSummary, Bucket, Currency, TxProc, TxProcConfig, TxRecord,
+    TxRecordFlags, TxRecordMetadata, WithdrawalDisputePolicy,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Lines, Read, Write};
+
+/// Available and held amounts of an account
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Balances {
+    pub available: Decimal,
+    pub held: Decimal,
+}
+
+impl Balances {
+    pub(crate) fn of(summary: &AccountSummary) -> Balances {
+        Balances {
+            available: summary.available_amount,
+            held: summary.held_amount,
+        }
+    }
+}
+
+/// State transition applied by [`TxProc`], see [`crate::TxProcConfig::journal`]
+///
+/// balances are given before and after the transition,
+/// the currency of the account is omitted for the default currency
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEvent {
+    /// first record seen for the client in the currency, even if the record itself is rejected
+    AccountOpened {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+    },
+    FundsDeposited {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        amount: Decimal,
+        before: Balances,
+        after: Balances,
+    },
+    FundsWithdrawn {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        amount: Decimal,
+        before: Balances,
+        after: Balances,
+    },
+    /// the withdrawal is declined for insufficient funds, the balances are unchanged
+    /// but the transaction id is recorded, it can not be used again nor disputed
+    WithdrawalDeclined {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        amount: Decimal,
+    },
+    /// funds moved from the client account to the destination account, opened beforehand if needed
+    FundsTransferred {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        destination: u16,
+        amount: Decimal,
+        before: Balances,
+        after: Balances,
+        destination_before: Balances,
+        destination_after: Balances,
+    },
+    /// the transfer is declined for insufficient funds, like a withdrawal, the destination account
+    /// is not opened
+    TransferDeclined {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        destination: u16,
+        amount: Decimal,
+    },
+    /// the transaction is disputed, `shortfall` is the part of a deposit that could not be held,
+    /// `policy` is the withdrawal dispute policy of a disputed withdrawal
+    FundsHeld {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+        shortfall: Decimal,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        policy: Option<WithdrawalDisputePolicy>,
+        before: Balances,
+        after: Balances,
+    },
+    /// the dispute on the transaction is resolved
+    FundsReleased {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        before: Balances,
+        after: Balances,
+    },
+    /// the disputed transaction is charged back, always followed by [`JournalEvent::AccountLocked`]
+    FundsChargedBack {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        before: Balances,
+        after: Balances,
+    },
+    AccountLocked {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+    },
+    /// the amount is moved from available to held under the authorization id `tx`
+    FundsAuthorized {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        amount: Decimal,
+        before: Balances,
+        after: Balances,
+    },
+    /// the authorization is declined for insufficient funds, like a withdrawal, it can not be settled
+    AuthorizationDeclined {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        amount: Decimal,
+    },
+    /// the authorized amount is settled, removed from held
+    FundsCaptured {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        amount: Decimal,
+        before: Balances,
+        after: Balances,
+    },
+    /// the authorized amount is released, moved back from held to available
+    FundsVoided {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        amount: Decimal,
+        before: Balances,
+        after: Balances,
+    },
+    /// the fee of the record `tx` moved from the client account to the house account,
+    /// opened beforehand if needed, see [`crate::FeeSchedule`]
+    FeeCharged {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        amount: Decimal,
+        house: u16,
+        before: Balances,
+        after: Balances,
+        house_before: Balances,
+        house_after: Balances,
+    },
+    /// an admin record corrected a balance of the account, see [`crate::TxRecord::ADJUSTMENT`]
+    FundsAdjusted {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        bucket: Bucket,
+        amount: Decimal,
+        reason: String,
+        before: Balances,
+        after: Balances,
+    },
+    /// an admin record reactivated the account, see [`crate::TxRecord::UNLOCK`]
+    AccountUnlocked {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        reason: String,
+    },
+    /// the charged back transfer is credited back to its source account, the client,
+    /// follows the [`JournalEvent::AccountLocked`] of the destination
+    FundsReturned {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        amount: Decimal,
+        before: Balances,
+        after: Balances,
+    },
+}
+
+impl TxProc {
+    /// rebuilds the state of a [`TxProc`] by replaying its journal, keeping the transactions in memory
+    pub fn replay(events: impl IntoIterator<Item = JournalEvent>) -> Result<TxProc, JournalError> {
+        TxProc::replay_with_store(TxProcConfig::default(), InMemoryStore::default(), events)
+    }
+}
+
+impl<S: TransactionStore> TxProc<S> {
+    /// rebuilds the state of a [`TxProc`] by replaying its journal into the given, empty, store
+    pub fn replay_with_store(
+        config: TxProcConfig,
+        transactions: S,
+        events: impl IntoIterator<Item = JournalEvent>,
+    ) -> Result<TxProc<S>, JournalError> {
+        let mut tx_proc = TxProc::with_store(config, transactions);
+
+        for event in events {
+            tx_proc.apply_journal_event(event)?;
+        }
+
+        Ok(tx_proc)
+    }
+
+    /// applies a single journal event, checking that the balances before the event match
+    ///
+    /// the event is not recorded in the journal of this [`TxProc`]
+    pub fn apply_journal_event(&mut self, event: JournalEvent) -> Result<(), JournalError> {
+        match event {
+            JournalEvent::AccountOpened { client, currency } => {
+                if self.account_summaries.contains_key(&(client, currency)) {
+                    return Err(JournalError::Inconsistent(format!(
+                        "account {} is already opened",
+                        account_name((client, currency))
+                    )));
+                }
+                self.account_summaries.insert(
+                    (client, currency),
+                    new_account(&self.credit_limits, (client, currency)),
+                );
+            }
+            JournalEvent::FundsDeposited {
+                client,
+                currency,
+                tx,
+                amount,
+                before,
+                after,
+            } => {
+                self.check_balances((client, currency), before)?;
+                self.record_transaction(TxRecord::DEPOSIT(
+                    TxRecordMetadata::new(client, tx).with_currency(currency),
+                    amount,
+                ))?;
+                self.set_balances((client, currency), after);
+            }
+            JournalEvent::FundsWithdrawn {
+                client,
+                currency,
+                tx,
+                amount,
+                before,
+                after,
+            } => {
+                self.check_balances((client, currency), before)?;
+                self.record_transaction(TxRecord::WITHDRAWAL(
+                    TxRecordMetadata::new(client, tx).with_currency(currency),
+                    amount,
+                ))?;
+                self.set_balances((client, currency), after);
+            }
+            JournalEvent::WithdrawalDeclined {
+                client,
+                currency,
+                tx,
+                amount,
+            } => {
+                self.opened_account((client, currency))?;
+                self.record_transaction(TxRecord::WITHDRAWAL(
+                    TxRecordMetadata::new(client, tx).with_currency(currency),
+                    amount,
+                ))?;
+                self.update_flags(tx, TxRecordFlags::default().with_declined())?;
+            }
+            JournalEvent::FundsTransferred {
+                client,
+                currency,
+                tx,
+                destination,
+                amount,
+                before,
+                after,
+                destination_before,
+                destination_after,
+            } => {
+                self.check_balances((client, currency), before)?;
+                self.check_balances((destination, currency), destination_before)?;
+                self.record_transaction(TxRecord::TRANSFER(
+                    TxRecordMetadata::new(client, tx).with_currency(currency),
+                    destination,
+                    amount,
+                ))?;
+                self.set_balances((client, currency), after);
+                self.set_balances((destination, currency), destination_after);
+            }
+            JournalEvent::TransferDeclined {
+                client,
+                currency,
+                tx,
+                destination,
+                amount,
+            } => {
+                self.opened_account((client, currency))?;
+                self.record_transaction(TxRecord::TRANSFER(
+                    TxRecordMetadata::new(client, tx).with_currency(currency),
+                    destination,
+                    amount,
+                ))?;
+                self.update_flags(tx, TxRecordFlags::default().with_declined())?;
+            }
+            JournalEvent::FundsHeld {
+                client,
+                currency,
+                tx,
+                shortfall,
+                policy,
+                before,
+                after,
+            } => {
+                self.check_balances((client, currency), before)?;
+                if policy.is_none()
+                    && matches!(
+                        self.transactions.get(tx)?,
+                        Some((TxRecord::WITHDRAWAL(..), _))
+                    )
+                {
+                    return Err(JournalError::Inconsistent(format!(
+                        "disputed withdrawal {} without dispute policy",
+                        tx
+                    )));
+                }
+                self.update_flags(
+                    tx,
+                    TxRecordFlags::new(true, false)
+                        .with_shortfall(shortfall)
+                        .with_withdrawal_dispute_policy(policy),
+                )?;
+                self.set_balances((client, currency), after);
+                self.opened_account((client, currency))?.shortfall += shortfall;
+            }
+            JournalEvent::FundsReleased {
+                client,
+                currency,
+                tx,
+                before,
+                after,
+            } => {
+                self.check_balances((client, currency), before)?;
+                let shortfall = self.update_flags(tx, TxRecordFlags::new(false, false))?;
+                self.set_balances((client, currency), after);
+                self.opened_account((client, currency))?.shortfall -= shortfall;
+            }
+            JournalEvent::FundsChargedBack {
+                client,
+                currency,
+                tx,
+                before,
+                after,
+            } => {
+                self.check_balances((client, currency), before)?;
+                let shortfall = self.update_flags(tx, TxRecordFlags::new(false, true))?;
+                self.set_balances((client, currency), after);
+                self.opened_account((client, currency))?.shortfall -= shortfall;
+            }
+            JournalEvent::AccountLocked { client, currency } => {
+                self.opened_account((client, currency))?.locked = true;
+            }
+            JournalEvent::FundsAuthorized {
+                client,
+                currency,
+                tx,
+                amount,
+                before,
+                after,
+            } => {
+                self.check_balances((client, currency), before)?;
+                self.record_transaction(TxRecord::AUTHORIZE(
+                    TxRecordMetadata::new(client, tx).with_currency(currency),
+                    amount,
+                ))?;
+                self.set_balances((client, currency), after);
+                self.opened_account((client, currency))?.authorized_amount += amount;
+            }
+            JournalEvent::AuthorizationDeclined {
+                client,
+                currency,
+                tx,
+                amount,
+            } => {
+                self.opened_account((client, currency))?;
+                self.record_transaction(TxRecord::AUTHORIZE(
+                    TxRecordMetadata::new(client, tx).with_currency(currency),
+                    amount,
+                ))?;
+                self.update_flags(tx, TxRecordFlags::default().with_declined())?;
+            }
+            JournalEvent::FundsCaptured {
+                client,
+                currency,
+                tx,
+                amount,
+                before,
+                after,
+            } => {
+                self.check_balances((client, currency), before)?;
+                self.update_flags(tx, TxRecordFlags::default().with_captured())?;
+                self.set_balances((client, currency), after);
+                self.opened_account((client, currency))?.authorized_amount -= amount;
+            }
+            JournalEvent::FundsVoided {
+                client,
+                currency,
+                tx,
+                amount,
+                before,
+                after,
+            } => {
+                self.check_balances((client, currency), before)?;
+                self.update_flags(tx, TxRecordFlags::default().with_voided())?;
+                self.set_balances((client, currency), after);
+                self.opened_account((client, currency))?.authorized_amount -= amount;
+            }
+            JournalEvent::FeeCharged {
+                client,
+                currency,
+                amount,
+                house,
+                before,
+                after,
+                house_before,
+                house_after,
+                ..
+            } => {
+                self.check_balances((client, currency), before)?;
+                self.check_balances((house, currency), house_before)?;
+                self.set_balances((client, currency), after);
+                self.set_balances((house, currency), house_after);
+                self.opened_account((client, currency))?.fees += amount;
+            }
+            JournalEvent::FundsAdjusted {
+                client,
+                currency,
+                tx,
+                bucket,
+                amount,
+                reason,
+                before,
+                after,
+            } => {
+                self.check_balances((client, currency), before)?;
+                self.record_transaction(TxRecord::ADJUSTMENT(
+                    TxRecordMetadata::new(client, tx).with_currency(currency),
+                    bucket,
+                    amount,
+                    reason,
+                ))?;
+                self.set_balances((client, currency), after);
+                let summary = self.opened_account((client, currency))?;
+                summary.adjustments += 1;
+                summary.adjusted_amount += amount;
+            }
+            JournalEvent::AccountUnlocked {
+                client, currency, ..
+            } => {
+                self.opened_account((client, currency))?.locked = false;
+            }
+            JournalEvent::FundsReturned {
+                client,
+                currency,
+                before,
+                after,
+                ..
+            } => {
+                self.check_balances((client, currency), before)?;
+                self.set_balances((client, currency), after);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn opened_account(&mut self, key: AccountKey) -> Result<&mut AccountSummary, JournalError> {
+        self.account_summaries.get_mut(&key).ok_or_else(|| {
+            JournalError::Inconsistent(format!("account {} is not opened", account_name(key)))
+        })
+    }
+
+    fn check_balances(&mut self, key: AccountKey, before: Balances) -> Result<(), JournalError> {
+        let balances = Balances::of(self.opened_account(key)?);
+        if balances != before {
+            return Err(JournalError::Inconsistent(format!(
+                "balances of account {} are {:?}, expected {:?}",
+                account_name(key),
+                balances,
+                before
+            )));
+        }
+        Ok(())
+    }
+
+    fn set_balances(&mut self, key: AccountKey, after: Balances) {
+        let summary = self
+            .account_summaries
+            .entry(key)
+            .or_insert_with(|| new_account(&self.credit_limits, key));
+        summary.available_amount = after.available;
+        summary.held_amount = after.held;
+    }
+
+    fn record_transaction(&mut self, record: TxRecord) -> Result<(), JournalError> {
+        let tx = record.record_metadata().tx_id;
+        if !self.transactions.insert_if_absent(record)? {
+            return Err(JournalError::Inconsistent(format!(
+                "transaction {} is already recorded",
+                tx
+            )));
+        }
+        Ok(())
+    }
+
+    // returns the shortfall of the previous flags
+    fn update_flags(&mut self, tx: u32, flags: TxRecordFlags) -> Result<Decimal, JournalError> {
+        let previous = match self.transactions.get(tx)? {
+            Some((_, previous)) => previous,
+            None => {
+                return Err(JournalError::Inconsistent(format!(
+                    "transaction {} is not recorded",
+                    tx
+                )))
+            }
+        };
+        self.transactions.update_flags(tx, flags)?;
+        Ok(previous.shortfall)
+    }
+}
+
+// client id, followed by the currency if any, e.g. `1 EUR`
+fn account_name((client, currency): AccountKey) -> String {
+    match currency.is_default() {
+        true => client.to_string(),
+        false => format!("{} {}", client, currency),
+    }
+}
+
+/// Writer encoding journal events as JSON lines
+pub struct JournalWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JournalWriter<W> {
+    pub fn new(writer: W) -> JournalWriter<W> {
+        JournalWriter { writer }
+    }
+
+    pub fn write(&mut self, event: &JournalEvent) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, event)?;
+        self.writer.write_all(b"\n")
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Streaming reader decoding journal events written by [`JournalWriter`]
+pub struct JournalReader<R: Read> {
+    lines: Lines<BufReader<R>>,
+    line: u64,
+}
+
+impl<R: Read> JournalReader<R> {
+    pub fn new(reader: R) -> JournalReader<R> {
+        JournalReader {
+            lines: BufReader::new(reader).lines(),
+            line: 0,
+        }
+    }
+}
+
+impl<R: Read> Iterator for JournalReader<R> {
+    type Item = Result<JournalEvent, JournalError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.lines.next()?;
+        self.line += 1;
+
+        Some(match line {
+            Ok(line) => serde_json::from_str(&line).map_err(|e| JournalError::Invalid {
+                line: self.line,
+                message: e.to_string(),
+            }),
+            Err(e) => Err(JournalError::Io(e)),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum JournalError {
+    Io(io::Error),
+    /// the journal line can not be decoded
+    Invalid {
+        line: u64,
+        message: String,
+    },
+    /// the event does not apply to the current state
+    Inconsistent(String),
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalError::Io(e) => write!(f, "journal i/o error: {}", e),
+            JournalError::Invalid { line, message } => {
+                write!(f, "invalid journal line {}: {}", line, message)
+            }
+            JournalError::Inconsistent(message) => write!(f, "inconsistent journal: {}", message),
+        }
+    }
+}
+
+impl Error for JournalError {}
+
+impl From<io::Error> for JournalError {
+    fn from(e: io::Error) -> JournalError {
+        JournalError::Io(e)
+    }
+}
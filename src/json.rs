@@ -0,0 +1,304 @@
+use crate::input::{InvalidRecord, RecordInput};
+use crate::scale::{InputScale, OutputScale};
+use crate::{AccountSummary, Currency, TxRecord};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Lines, Read, Write};
+
+/// Streaming reader decoding JSON lines into [`TxRecord`]s
+///
+/// each line is an object such as `{"type": "deposit", "client": 1, "tx": 1, "amount": "1.5"}`,
+/// amounts can also be given as JSON numbers, blank lines are skipped
+///
+/// lines that can not be decoded are yielded as errors, the following lines are still read
+pub struct TxRecordJsonReader<R: Read> {
+    lines: Lines<BufReader<R>>,
+    line: u64,
+    record: String,
+    scale: Option<InputScale>,
+    admin_records: bool,
+}
+
+impl<R: Read> TxRecordJsonReader<R> {
+    pub fn new(reader: R) -> TxRecordJsonReader<R> {
+        TxRecordJsonReader {
+            lines: BufReader::new(reader).lines(),
+            line: 0,
+            record: String::new(),
+            scale: None,
+            admin_records: true,
+        }
+    }
+
+    /// rounds or rejects the amounts with more decimal places than the scale
+    pub fn with_scale(mut self, scale: InputScale) -> TxRecordJsonReader<R> {
+        self.scale = Some(scale);
+        self
+    }
+
+    /// yields the admin records, such as `unlock`, as errors, e.g. for end-user inputs
+    pub fn without_admin_records(mut self) -> TxRecordJsonReader<R> {
+        self.admin_records = false;
+        self
+    }
+
+    /// line number, starting at 1, of the last record yielded by the iterator
+    pub fn line(&self) -> Option<u64> {
+        Some(self.line)
+    }
+
+    /// last record yielded by the iterator, trimmed
+    pub fn raw_record(&self) -> String {
+        self.record.clone()
+    }
+}
+
+impl<R: Read> Iterator for TxRecordJsonReader<R> {
+    type Item = Result<TxRecord, JsonLineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = self.lines.next()?;
+            self.line += 1;
+
+            let kind = match line {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => {
+                    self.record = line.trim().to_string();
+                    match serde_json::from_str::<RecordInput>(&self.record) {
+                        Ok(record) => {
+                            match record.decode(self.scale.as_ref(), self.admin_records) {
+                                Ok(record) => return Some(Ok(record)),
+                                Err(e) => JsonLineErrorKind::Invalid(e),
+                            }
+                        }
+                        Err(e) => JsonLineErrorKind::Json(e),
+                    }
+                }
+                Err(e) => {
+                    self.record.clear();
+                    JsonLineErrorKind::Io(e)
+                }
+            };
+
+            return Some(Err(JsonLineError {
+                line: self.line,
+                kind,
+            }));
+        }
+    }
+}
+
+/// A JSON line that could not be decoded into a [`TxRecord`]
+#[derive(Debug)]
+pub struct JsonLineError {
+    line: u64,
+    kind: JsonLineErrorKind,
+}
+
+#[derive(Debug)]
+pub enum JsonLineErrorKind {
+    Io(io::Error),
+    /// malformed line: invalid JSON, missing field, unparsable id or amount, ..
+    Json(serde_json::Error),
+    /// the record does not pass the validation shared with the CSV input
+    Invalid(InvalidRecord),
+}
+
+impl JsonLineError {
+    /// line number in the input, starting at 1
+    pub fn line(&self) -> Option<u64> {
+        Some(self.line)
+    }
+
+    pub fn kind(&self) -> &JsonLineErrorKind {
+        &self.kind
+    }
+
+    /// stable, machine-readable identifier of the error kind, shared with [`crate::csv::CsvLineError::code`]
+    pub fn code(&self) -> &'static str {
+        match &self.kind {
+            JsonLineErrorKind::Io(_) => "io_error",
+            JsonLineErrorKind::Json(_) => "malformed_line",
+            JsonLineErrorKind::Invalid(e) => e.code(),
+        }
+    }
+}
+
+impl fmt::Display for JsonLineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            JsonLineErrorKind::Io(e) => write!(f, "i/o error on line {}: {}", self.line, e),
+            JsonLineErrorKind::Json(e) => write!(f, "json error on line {}: {}", self.line, e),
+            JsonLineErrorKind::Invalid(e) => write!(f, "{} on line {}", e, self.line),
+        }
+    }
+}
+
+impl Error for JsonLineError {}
+
+#[derive(Serialize)]
+struct JsonOutput {
+    client: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<Currency>,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    credit_limit: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headroom: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shortfall: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    negative: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    adjustments: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    adjusted: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fees: Option<Decimal>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    authorized: Option<Decimal>,
+}
+
+/// Writer encoding account summaries as JSON objects with the fields of the CSV output
+///
+/// amounts are encoded as strings to keep their exact value
+pub struct AccountSummaryJsonWriter<W: Write> {
+    writer: W,
+    lines: bool,
+    count: usize,
+    scale: Option<OutputScale>,
+    currency: bool,
+    credit_limit: bool,
+    negative: bool,
+    adjustments: bool,
+    fees: bool,
+    authorizations: bool,
+}
+
+impl<W: Write> AccountSummaryJsonWriter<W> {
+    /// writes a single JSON array, completed by [`AccountSummaryJsonWriter::finish`]
+    pub fn array(writer: W) -> AccountSummaryJsonWriter<W> {
+        AccountSummaryJsonWriter {
+            writer,
+            lines: false,
+            count: 0,
+            scale: None,
+            currency: false,
+            credit_limit: false,
+            negative: false,
+            adjustments: false,
+            fees: false,
+            authorizations: false,
+        }
+    }
+
+    /// writes one JSON object per line
+    pub fn lines(writer: W) -> AccountSummaryJsonWriter<W> {
+        AccountSummaryJsonWriter {
+            writer,
+            lines: true,
+            count: 0,
+            scale: None,
+            currency: false,
+            credit_limit: false,
+            negative: false,
+            adjustments: false,
+            fees: false,
+            authorizations: false,
+        }
+    }
+
+    /// writes the amounts with exactly the decimal places of the scale
+    pub fn with_scale(mut self, scale: OutputScale) -> AccountSummaryJsonWriter<W> {
+        self.scale = Some(scale);
+        self
+    }
+
+    /// adds a `currency` field after the client, empty for the default currency
+    pub fn with_currency(mut self) -> AccountSummaryJsonWriter<W> {
+        self.currency = true;
+        self
+    }
+
+    /// adds `credit_limit` and `headroom` fields after `locked`
+    pub fn with_credit_limit(mut self) -> AccountSummaryJsonWriter<W> {
+        self.credit_limit = true;
+        self
+    }
+
+    /// adds `shortfall` and `negative` fields at the end, see [`AccountSummary::is_negative`]
+    pub fn with_negative_flag(mut self) -> AccountSummaryJsonWriter<W> {
+        self.negative = true;
+        self
+    }
+
+    /// adds `adjustments` and `adjusted` fields at the end, counting and summing the adjustments
+    pub fn with_adjustments(mut self) -> AccountSummaryJsonWriter<W> {
+        self.adjustments = true;
+        self
+    }
+
+    /// adds a `fees` field at the end, the total of the fees charged to the account
+    pub fn with_fees(mut self) -> AccountSummaryJsonWriter<W> {
+        self.fees = true;
+        self
+    }
+
+    /// adds an `authorized` field at the end, the part of `held` reserved by authorizations
+    pub fn with_authorizations(mut self) -> AccountSummaryJsonWriter<W> {
+        self.authorizations = true;
+        self
+    }
+
+    pub fn write(&mut self, client_id: u16, summary: &AccountSummary) -> io::Result<()> {
+        if !self.lines {
+            self.writer
+                .write_all(if self.count == 0 { b"[" } else { b"," })?;
+        }
+        let scaled = |amount| match &self.scale {
+            Some(scale) => scale.apply(amount),
+            None => amount,
+        };
+        let output = JsonOutput {
+            client: client_id,
+            currency: self.currency.then(|| summary.currency()),
+            available: scaled(summary.available_amount()),
+            held: scaled(summary.held_amount()),
+            total: scaled(summary.total_amount()),
+            locked: summary.is_locked(),
+            credit_limit: self.credit_limit.then(|| scaled(summary.credit_limit())),
+            headroom: self.credit_limit.then(|| scaled(summary.headroom())),
+            shortfall: self.negative.then(|| scaled(summary.shortfall())),
+            negative: self.negative.then(|| summary.is_negative()),
+            adjustments: self.adjustments.then(|| summary.adjustments()),
+            adjusted: self.adjustments.then(|| scaled(summary.adjusted_amount())),
+            fees: self.fees.then(|| scaled(summary.fees())),
+            authorized: self
+                .authorizations
+                .then(|| scaled(summary.authorized_amount())),
+        };
+
+        serde_json::to_writer(&mut self.writer, &output)?;
+        if self.lines {
+            self.writer.write_all(b"\n")?;
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// closes the JSON array if any, then flushes
+    pub fn finish(&mut self) -> io::Result<()> {
+        if !self.lines {
+            self.writer
+                .write_all(if self.count == 0 { b"[]\n" } else { b"]\n" })?;
+        }
+        self.writer.flush()
+    }
+}
@@ -1,17 +1,59 @@
+pub mod csv;
+pub mod input;
+pub mod journal;
+pub mod json;
+pub mod limits;
+pub mod rejects;
+pub mod scale;
+pub mod server;
+pub mod sharded;
+pub mod snapshot;
+pub mod store;
+
+use journal::{Balances, JournalEvent};
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
-use std::collections::HashMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use store::{InMemoryStore, TransactionStore};
 
 // todo evaluate whether using newtype structs improves safety without making the code harder to read
 // e.g: HeldAmount(Decimal), AvailableAmount(Decimal), Locked(bool)
 #[derive(Default)]
 pub struct AccountSummary {
+    currency: Currency,
     held_amount: Decimal,
     available_amount: Decimal,
     locked: bool,
+    credit_limit: Decimal,
+    // disputed amounts that could not be held, see DepositDisputePolicy::HoldAvailable
+    shortfall: Decimal,
+    // number and net amount of the adjustment records applied to the account
+    adjustments: u32,
+    adjusted_amount: Decimal,
+    // total of the fees charged to the account, see FeeSchedule
+    fees: Decimal,
+    // part of the held amount reserved by pending authorizations
+    authorized_amount: Decimal,
 }
 
 impl AccountSummary {
+    fn new(currency: Currency, credit_limit: Decimal) -> AccountSummary {
+        AccountSummary {
+            currency,
+            credit_limit,
+            ..AccountSummary::default()
+        }
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
     pub fn total_amount(&self) -> Decimal {
         self.held_amount() + self.available_amount()
     }
@@ -25,151 +67,1370 @@ impl AccountSummary {
     pub fn is_locked(&self) -> bool {
         self.locked
     }
+
+    /// how far withdrawals can take the available amount below zero, see [`TxProc::set_credit_limit`]
+    pub fn credit_limit(&self) -> Decimal {
+        self.credit_limit
+    }
+
+    /// amount that can still be withdrawn, negative when a dispute took the account over its limit
+    pub fn headroom(&self) -> Decimal {
+        self.available_amount + self.credit_limit
+    }
+
+    /// disputed amounts that could not be held, see [`DepositDisputePolicy::HoldAvailable`]
+    pub fn shortfall(&self) -> Decimal {
+        self.shortfall
+    }
+
+    /// the available amount is negative, or would be without the shortfall
+    pub fn is_negative(&self) -> bool {
+        self.available_amount - self.shortfall < dec!(0)
+    }
+
+    /// number of [`TxRecord::ADJUSTMENT`] applied to the account
+    pub fn adjustments(&self) -> u32 {
+        self.adjustments
+    }
+
+    /// net amount of the [`TxRecord::ADJUSTMENT`] applied to the account, both balances included
+    pub fn adjusted_amount(&self) -> Decimal {
+        self.adjusted_amount
+    }
+
+    /// total of the fees charged to the account, see [`FeeSchedule`]
+    pub fn fees(&self) -> Decimal {
+        self.fees
+    }
+
+    /// part of the held amount reserved by the [`TxRecord::AUTHORIZE`] not yet captured nor voided,
+    /// the rest is held by disputes
+    pub fn authorized_amount(&self) -> Decimal {
+        self.authorized_amount
+    }
 }
 
-#[derive(Default)]
-struct TxRecordFlags {
+/// Dispute state of a processed transaction, or settlement state of an authorization
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TxRecordFlags {
     disputed: bool,
     chargedback: bool,
+    shortfall: Decimal,
+    // settlement of an authorization
+    captured: bool,
+    voided: bool,
+    // withdrawal, transfer or authorization declined for insufficient funds
+    declined: bool,
+    // policy of the open dispute on a withdrawal
+    withdrawal_dispute_policy: Option<WithdrawalDisputePolicy>,
 }
 
-#[derive(Default)]
-pub struct TxProc {
-    account_summaries: HashMap<u16, AccountSummary>,
-    transactions: HashMap<u32, (TxRecord, TxRecordFlags)>,
+impl TxRecordFlags {
+    pub fn new(disputed: bool, chargedback: bool) -> TxRecordFlags {
+        TxRecordFlags {
+            disputed,
+            chargedback,
+            ..TxRecordFlags::default()
+        }
+    }
+
+    /// the authorization has been settled by a [`TxRecord::CAPTURE`]
+    pub fn with_captured(mut self) -> TxRecordFlags {
+        self.captured = true;
+        self
+    }
+
+    /// the authorization has been released by a [`TxRecord::VOID`]
+    pub fn with_voided(mut self) -> TxRecordFlags {
+        self.voided = true;
+        self
+    }
+
+    /// the withdrawal, transfer or authorization has been declined, its transaction id stays used
+    /// but it can not be disputed, nor captured or voided
+    pub fn with_declined(mut self) -> TxRecordFlags {
+        self.declined = true;
+        self
+    }
+
+    pub fn is_captured(&self) -> bool {
+        self.captured
+    }
+
+    pub fn is_voided(&self) -> bool {
+        self.voided
+    }
+
+    pub fn is_declined(&self) -> bool {
+        self.declined
+    }
+
+    /// the disputed withdrawal is resolved or charged back under the policy it was disputed with,
+    /// whatever the current [`TxProcConfig::withdrawal_dispute_policy`]
+    pub fn with_withdrawal_dispute_policy(
+        mut self,
+        policy: Option<WithdrawalDisputePolicy>,
+    ) -> TxRecordFlags {
+        self.withdrawal_dispute_policy = policy;
+        self
+    }
+
+    pub fn withdrawal_dispute_policy(&self) -> Option<WithdrawalDisputePolicy> {
+        self.withdrawal_dispute_policy
+    }
+
+    /// part of the disputed amount that could not be held, see [`DepositDisputePolicy::HoldAvailable`]
+    pub fn with_shortfall(mut self, shortfall: Decimal) -> TxRecordFlags {
+        self.shortfall = shortfall;
+        self
+    }
+
+    pub fn shortfall(&self) -> Decimal {
+        self.shortfall
+    }
+
+    pub fn is_disputed(&self) -> bool {
+        self.disputed
+    }
+
+    pub fn is_chargedback(&self) -> bool {
+        self.chargedback
+    }
+}
+
+/// Defines how disputes, resolves and chargebacks on a withdrawal move funds
+///
+/// the policy is recorded with the dispute, it still applies to the resolve or the chargeback
+/// when the configuration changes in between
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WithdrawalDisputePolicy {
+    /// only deposits can be disputed, records targeting a withdrawal are rejected
+    #[default]
+    Reject,
+    /// dispute: the withdrawn amount is credited back into held
+    ///
+    /// resolve: the withdrawn amount is removed from held, the withdrawal stands
+    ///
+    /// chargeback: the withdrawn amount moves from held to available and the account is locked
+    HoldReversal,
+    /// dispute: the withdrawn amount is provisionally credited back into available
+    ///
+    /// resolve: the provisional credit is debited from available, the withdrawal stands
+    ///
+    /// chargeback: the provisional credit becomes final and the account is locked
+    ProvisionalCredit,
+}
+
+/// Defines how a dispute on a deposit moves funds when the available amount does not cover it,
+/// e.g. when the deposited funds have already been withdrawn
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DepositDisputePolicy {
+    /// the whole amount is held, the available amount goes negative
+    #[default]
+    AllowNegative,
+    /// the dispute is rejected
+    Reject,
+    /// only the available amount, if positive, is held, the rest is recorded as a shortfall
+    ///
+    /// resolve: the held part is released and the shortfall cleared
+    ///
+    /// chargeback: the held part is removed and the shortfall is debited from available
+    HoldAvailable,
+}
+
+impl FromStr for DepositDisputePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DepositDisputePolicy, String> {
+        match s {
+            "allow-negative" => Ok(DepositDisputePolicy::AllowNegative),
+            "reject" => Ok(DepositDisputePolicy::Reject),
+            "hold-available" => Ok(DepositDisputePolicy::HoldAvailable),
+            _ => Err(format!("unknown deposit dispute policy: {}", s)),
+        }
+    }
+}
+
+/// Fees charged to the client accounts and credited to the house account, in the currency of the record
+///
+/// a fee is a separate movement, applied once the record itself is applied,
+/// the records of the house account are free
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FeeSchedule {
+    /// client id of the house account, in every currency
+    pub house_client: u16,
+    /// flat part of the withdrawal fee
+    pub withdrawal_flat: Decimal,
+    /// part of the withdrawn amount added to the withdrawal fee, e.g. `0.01` for 1%
+    pub withdrawal_rate: Decimal,
+    /// charged to the disputing client when a dispute is applied
+    pub dispute: Decimal,
+    /// charged when a chargeback is applied, after the account is locked
+    pub chargeback: Decimal,
+}
+
+impl FeeSchedule {
+    /// fee charged to the client of the record if it is applied
+    ///
+    /// a withdrawal is declined when the available amount does not cover the amount and the fee,
+    /// a dispute or a chargeback fee can take the available amount negative
+    ///
+    /// zero for a capture, charged the [`FeeSchedule::withdrawal_fee`] of its authorization amount,
+    /// which the record alone does not give
+    pub fn fee(&self, record: &TxRecord) -> Decimal {
+        if record.record_metadata().client_id == self.house_client {
+            return dec!(0);
+        }
+        match *record {
+            TxRecord::WITHDRAWAL(_, amount) => self.withdrawal_fee(amount),
+            TxRecord::DISPUTE(_) => self.dispute,
+            TxRecord::CHARGEBACK(_) => self.chargeback,
+            _ => dec!(0),
+        }
+    }
+
+    /// fee of a withdrawal, or of the capture of an authorization, of the amount
+    pub fn withdrawal_fee(&self, amount: Decimal) -> Decimal {
+        self.withdrawal_flat + amount * self.withdrawal_rate
+    }
+
+    /// whether the record can be charged a fee, including the captures
+    pub fn may_charge(&self, record: &TxRecord) -> bool {
+        match record {
+            TxRecord::CAPTURE(metadata) => {
+                metadata.client_id != self.house_client
+                    && !(self.withdrawal_flat.is_zero() && self.withdrawal_rate.is_zero())
+            }
+            _ => !self.fee(record).is_zero(),
+        }
+    }
+
+    /// whether any record can be charged a fee
+    pub fn charges_fees(&self) -> bool {
+        [
+            self.withdrawal_flat,
+            self.withdrawal_rate,
+            self.dispute,
+            self.chargeback,
+        ]
+        .iter()
+        .any(|fee| !fee.is_zero())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TxProcConfig {
+    pub withdrawal_dispute_policy: WithdrawalDisputePolicy,
+    pub deposit_dispute_policy: DepositDisputePolicy,
+    pub fees: FeeSchedule,
+    /// records a [`JournalEvent`] for every state transition, see [`TxProc::drain_journal`]
+    pub journal: bool,
+    /// stops processing at the first rejected record: the rejected record leaves no trace,
+    /// not even an opened account, and every later record is rejected with [`TxRejection::Halted`]
+    pub strict: bool,
+}
+
+pub struct TxProc<S: TransactionStore = InMemoryStore> {
+    config: TxProcConfig,
+    // ordered by client id, so that summaries are always listed in the same order
+    account_summaries: BTreeMap<AccountKey, AccountSummary>,
+    transactions: S,
+    journal: Vec<JournalEvent>,
+    // set by the first rejection in strict mode
+    halted: bool,
+    // limits of the accounts, including the ones that are not opened yet
+    credit_limits: HashMap<AccountKey, Decimal>,
+}
+
+impl Default for TxProc {
+    fn default() -> TxProc {
+        TxProc::with_config(TxProcConfig::default())
+    }
 }
 
 impl TxProc {
-    // todo if needed, it's possible to return a Result
-    pub fn submit_tx_record(&mut self, record: TxRecord) {
-        // because try_insert is nightly-only, see https://github.com/rust-lang/rust/issues/82766
-        fn record_transaction_if_new(
-            transactions: &mut HashMap<u32, (TxRecord, TxRecordFlags)>,
-            record: TxRecord,
-        ) -> Result<(), ()> {
-            match transactions.get(&record.record_metadata().tx_id) {
-                Some(_) => Err(()),
-                None => {
-                    transactions.insert(
-                        record.record_metadata().tx_id,
-                        (record, TxRecordFlags::default()),
-                    );
-                    Ok(())
+    pub fn with_config(config: TxProcConfig) -> TxProc {
+        TxProc::with_store(config, InMemoryStore::default())
+    }
+}
+
+impl<S: TransactionStore> TxProc<S> {
+    pub fn with_store(config: TxProcConfig, transactions: S) -> TxProc<S> {
+        TxProc {
+            config,
+            account_summaries: BTreeMap::new(),
+            transactions,
+            journal: Vec::new(),
+            halted: false,
+            credit_limits: HashMap::new(),
+        }
+    }
+
+    /// allows withdrawals to take the available amount of the client account in the currency
+    /// down to `-limit`, the account may not be opened yet, negative limits are set to zero
+    ///
+    /// like the configuration, credit limits are not part of the snapshots
+    pub fn set_credit_limit(&mut self, client_id: u16, currency: Currency, limit: Decimal) {
+        let limit = limit.max(dec!(0));
+        self.credit_limits.insert((client_id, currency), limit);
+        if let Some(summary) = self.account_summaries.get_mut(&(client_id, currency)) {
+            summary.credit_limit = limit;
+        }
+    }
+
+    pub fn submit_tx_record(&mut self, record: TxRecord) -> Result<Applied, TxRejection> {
+        if self.halted {
+            return Err(TxRejection::Halted);
+        }
+
+        if !self.config.strict {
+            return self.journal_tx_record(record);
+        }
+
+        let key = match self.account_key(&record) {
+            Ok(key) => key,
+            Err(rejection) => {
+                self.halted = true;
+                return Err(rejection);
+            }
+        };
+        let opened = self.account_summaries.contains_key(&key);
+        let journal_len = self.journal.len();
+
+        let result = self.journal_tx_record(record);
+
+        if result.is_err() {
+            self.halted = true;
+            if !opened {
+                self.account_summaries.remove(&key);
+            }
+            self.journal.truncate(journal_len);
+        }
+
+        result
+    }
+
+    // account of the record: the client account in the currency of the deposit, the withdrawal,
+    // the transfer source or the authorization, or of the transaction referenced by a dispute,
+    // a resolve, a chargeback, a capture or a void
+    fn account_key(&self, record: &TxRecord) -> Result<AccountKey, TxRejection> {
+        let metadata = record.record_metadata();
+        let currency = match record {
+            TxRecord::DEPOSIT(..)
+            | TxRecord::WITHDRAWAL(..)
+            | TxRecord::TRANSFER(..)
+            | TxRecord::AUTHORIZE(..)
+            | TxRecord::UNLOCK(..)
+            | TxRecord::ADJUSTMENT(..) => metadata.currency,
+            _ => match self.transactions.get(metadata.tx_id)? {
+                Some((referenced, _)) if disputing_client(&referenced) == metadata.client_id => {
+                    referenced.record_metadata().currency
+                }
+                // the record is rejected, on the account of its own currency
+                _ => metadata.currency,
+            },
+        };
+        Ok((metadata.client_id, currency))
+    }
+
+    /// whether a rejection stopped the processing, see [`TxProcConfig::strict`]
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    fn journal_tx_record(&mut self, record: TxRecord) -> Result<Applied, TxRejection> {
+        let key = self.account_key(&record)?;
+        let tx = record.record_metadata().tx_id;
+        let fee = self.record_fee(&record)?;
+
+        if !self.config.journal {
+            let applied = self.apply_tx_record(record, key)?;
+            self.charge_fee(key, tx, fee);
+            return Ok(applied);
+        }
+
+        let (client, currency) = key;
+        let amount = match record {
+            TxRecord::DEPOSIT(_, amount)
+            | TxRecord::WITHDRAWAL(_, amount)
+            | TxRecord::TRANSFER(_, _, amount)
+            | TxRecord::AUTHORIZE(_, amount)
+            | TxRecord::ADJUSTMENT(_, _, amount, _) => amount,
+            TxRecord::CAPTURE(ref metadata) | TxRecord::VOID(ref metadata) => {
+                match self.transactions.get(metadata.tx_id)? {
+                    Some((TxRecord::AUTHORIZE(_, amount), _)) => amount,
+                    _ => dec!(0),
                 }
             }
+            _ => dec!(0),
+        };
+
+        // other account moved by the record, with its balances if opened
+        let counterpart = self.counterpart(&record, key)?;
+        let counterpart_before = counterpart.and_then(|(counterpart_key, _)| {
+            self.account_summaries
+                .get(&counterpart_key)
+                .map(Balances::of)
+        });
+
+        let opened = self.account_summaries.contains_key(&key);
+        let before = self
+            .account_summaries
+            .get(&key)
+            .map_or_else(Balances::default, Balances::of);
+
+        let shortfall_before = self
+            .account_summaries
+            .get(&key)
+            .map_or(dec!(0), |summary| summary.shortfall);
+
+        let (bucket, reason) = match record {
+            TxRecord::UNLOCK(_, ref reason) => (Bucket::default(), reason.clone()),
+            TxRecord::ADJUSTMENT(_, bucket, _, ref reason) => (bucket, reason.clone()),
+            _ => (Bucket::default(), String::new()),
+        };
+
+        let declined = match record {
+            TxRecord::WITHDRAWAL(..) => Some(JournalEvent::WithdrawalDeclined {
+                client,
+                currency,
+                tx,
+                amount,
+            }),
+            TxRecord::TRANSFER(_, destination, _) => Some(JournalEvent::TransferDeclined {
+                client,
+                currency,
+                tx,
+                destination,
+                amount,
+            }),
+            TxRecord::AUTHORIZE(..) => Some(JournalEvent::AuthorizationDeclined {
+                client,
+                currency,
+                tx,
+                amount,
+            }),
+            _ => None,
+        };
+
+        let result = self.apply_tx_record(record, key);
+
+        // a rejected record may still open the account
+        if !opened && self.account_summaries.contains_key(&key) {
+            self.journal
+                .push(JournalEvent::AccountOpened { client, currency });
+        }
+
+        // the declined record is recorded, so is its event
+        if let (Some(event), Err(TxRejection::InsufficientFunds)) = (declined, &result) {
+            self.journal.push(event);
         }
 
+        let applied = result?;
+
+        let after = Balances::of(&self.account_summaries[&key]);
+        let shortfall = self.account_summaries[&key].shortfall - shortfall_before;
+        let policy = match applied {
+            Applied::Disputed => self
+                .transactions
+                .get(tx)?
+                .and_then(|(_, flags)| flags.withdrawal_dispute_policy),
+            _ => None,
+        };
+
+        if let (Applied::Transferred, Some(((destination, _), _)), None) =
+            (applied, counterpart, counterpart_before)
+        {
+            self.journal.push(JournalEvent::AccountOpened {
+                client: destination,
+                currency,
+            });
+        }
+
+        self.journal.push(match applied {
+            Applied::Deposited => JournalEvent::FundsDeposited {
+                client,
+                currency,
+                tx,
+                amount,
+                before,
+                after,
+            },
+            Applied::Withdrawn => JournalEvent::FundsWithdrawn {
+                client,
+                currency,
+                tx,
+                amount,
+                before,
+                after,
+            },
+            Applied::Transferred => {
+                let ((destination, _), _) = counterpart.expect("transfer counterpart");
+                JournalEvent::FundsTransferred {
+                    client,
+                    currency,
+                    tx,
+                    destination,
+                    amount,
+                    before,
+                    after,
+                    destination_before: counterpart_before.unwrap_or_default(),
+                    destination_after: Balances::of(
+                        &self.account_summaries[&(destination, currency)],
+                    ),
+                }
+            }
+            Applied::Disputed => JournalEvent::FundsHeld {
+                client,
+                currency,
+                tx,
+                shortfall,
+                policy,
+                before,
+                after,
+            },
+            Applied::Resolved => JournalEvent::FundsReleased {
+                client,
+                currency,
+                tx,
+                before,
+                after,
+            },
+            Applied::ChargedBack => JournalEvent::FundsChargedBack {
+                client,
+                currency,
+                tx,
+                before,
+                after,
+            },
+            Applied::Unlocked => JournalEvent::AccountUnlocked {
+                client,
+                currency,
+                tx,
+                reason,
+            },
+            Applied::Authorized => JournalEvent::FundsAuthorized {
+                client,
+                currency,
+                tx,
+                amount,
+                before,
+                after,
+            },
+            Applied::Captured => JournalEvent::FundsCaptured {
+                client,
+                currency,
+                tx,
+                amount,
+                before,
+                after,
+            },
+            Applied::Voided => JournalEvent::FundsVoided {
+                client,
+                currency,
+                tx,
+                amount,
+                before,
+                after,
+            },
+            Applied::Adjusted => JournalEvent::FundsAdjusted {
+                client,
+                currency,
+                tx,
+                bucket,
+                amount,
+                reason,
+                before,
+                after,
+            },
+        });
+
+        if applied == Applied::ChargedBack {
+            self.journal
+                .push(JournalEvent::AccountLocked { client, currency });
+
+            if let Some((source_key, amount)) = counterpart {
+                self.journal.push(JournalEvent::FundsReturned {
+                    client: source_key.0,
+                    currency: source_key.1,
+                    tx,
+                    amount,
+                    before: counterpart_before.unwrap_or_default(),
+                    after: Balances::of(&self.account_summaries[&source_key]),
+                });
+            }
+        }
+
+        self.charge_fee(key, tx, fee);
+
+        Ok(applied)
+    }
+
+    // fee charged to the client of the record if it is applied
+    fn record_fee(&self, record: &TxRecord) -> Result<Decimal, TxRejection> {
+        if !self.config.fees.may_charge(record) {
+            return Ok(dec!(0));
+        }
+        Ok(match record {
+            TxRecord::CAPTURE(metadata) => match self.transactions.get(metadata.tx_id)? {
+                Some((TxRecord::AUTHORIZE(_, amount), _)) => {
+                    self.config.fees.withdrawal_fee(amount)
+                }
+                _ => dec!(0),
+            },
+            _ => self.config.fees.fee(record),
+        })
+    }
+
+    // moves the fee of an applied record from the client account to the house account,
+    // opened if needed, even if either account is locked
+    fn charge_fee(&mut self, key: AccountKey, tx: u32, fee: Decimal) {
+        if fee.is_zero() {
+            return;
+        }
+
+        let house_key = (self.config.fees.house_client, key.1);
+        let house_before = self.account_summaries.get(&house_key).map(Balances::of);
+
         let summary = self
             .account_summaries
-            .entry(record.record_metadata().client_id)
-            .or_default();
+            .get_mut(&key)
+            .expect("account of an applied record");
+        let before = Balances::of(summary);
+        summary.available_amount -= fee;
+        summary.fees += fee;
+        let after = Balances::of(summary);
+
+        let house = self
+            .account_summaries
+            .entry(house_key)
+            .or_insert_with(|| new_account(&self.credit_limits, house_key));
+        house.available_amount += fee;
+        let house_after = Balances::of(house);
 
-        if summary.is_locked() {
+        if !self.config.journal {
             return;
         }
 
+        if house_before.is_none() {
+            self.journal.push(JournalEvent::AccountOpened {
+                client: house_key.0,
+                currency: house_key.1,
+            });
+        }
+        self.journal.push(JournalEvent::FeeCharged {
+            client: key.0,
+            currency: key.1,
+            tx,
+            amount: fee,
+            house: house_key.0,
+            before,
+            after,
+            house_before: house_before.unwrap_or_default(),
+            house_after,
+        });
+    }
+
+    // account of the destination of a transfer, or of the source of a charged back transfer,
+    // with the transferred amount
+    fn counterpart(
+        &self,
+        record: &TxRecord,
+        key: AccountKey,
+    ) -> Result<Option<(AccountKey, Decimal)>, TxRejection> {
+        Ok(match *record {
+            TxRecord::TRANSFER(_, destination, amount) => Some(((destination, key.1), amount)),
+            TxRecord::CHARGEBACK(ref metadata) => match self.transactions.get(metadata.tx_id)? {
+                Some((TxRecord::TRANSFER(source, _, amount), _)) => {
+                    Some(((source.client_id, source.currency), amount))
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    /// events recorded since the last call, when [`TxProcConfig::journal`] is enabled
+    ///
+    /// events accumulate in memory until they are drained
+    pub fn drain_journal(&mut self) -> impl Iterator<Item = JournalEvent> + '_ {
+        self.journal.drain(..)
+    }
+
+    fn apply_tx_record(
+        &mut self,
+        record: TxRecord,
+        key: AccountKey,
+    ) -> Result<Applied, TxRejection> {
+        // the only record applying to a locked account, it does not open an account
+        if let TxRecord::UNLOCK(..) = record {
+            return match self.account_summaries.get_mut(&key) {
+                Some(summary) if summary.is_locked() => {
+                    summary.locked = false;
+                    Ok(Applied::Unlocked)
+                }
+                _ => Err(TxRejection::NotLocked),
+            };
+        }
+
+        fn record_transaction_if_new(
+            transactions: &mut impl TransactionStore,
+            record: TxRecord,
+        ) -> Result<(), TxRejection> {
+            match transactions.insert_if_absent(record)? {
+                true => Ok(()),
+                false => Err(TxRejection::DuplicateTransaction),
+            }
+        }
+
+        // a record declined for insufficient funds keeps its transaction id, flagged so that it can
+        // not be disputed nor settled, except in strict mode where the rejected record leaves no trace
+        fn decline(
+            transactions: &mut impl TransactionStore,
+            record: TxRecord,
+            strict: bool,
+        ) -> Result<Applied, TxRejection> {
+            if !strict {
+                let tx_id = record.record_metadata().tx_id;
+                record_transaction_if_new(transactions, record)?;
+                transactions.update_flags(tx_id, TxRecordFlags::default().with_declined())?;
+            }
+            Err(TxRejection::InsufficientFunds)
+        }
+
+        // checked before the summary of the source account is borrowed
+        let destination_locked = match record {
+            TxRecord::TRANSFER(_, destination, _) => self
+                .account_summaries
+                .get(&(destination, key.1))
+                .is_some_and(AccountSummary::is_locked),
+            _ => false,
+        };
+
+        let summary = self
+            .account_summaries
+            .entry(key)
+            .or_insert_with(|| new_account(&self.credit_limits, key));
+
+        // admin records also apply to locked accounts, so do the settlements of the authorizations
+        // which would otherwise keep their amount held
+        let settlement = matches!(record, TxRecord::CAPTURE(..) | TxRecord::VOID(..));
+        if summary.is_locked() && !record.is_admin() && !settlement {
+            return Err(TxRejection::AccountLocked);
+        }
+
         match record {
             TxRecord::DEPOSIT(_, amount) => {
                 if amount < dec!(0) {
-                    return;
+                    return Err(TxRejection::NegativeAmount);
                 }
 
-                match record_transaction_if_new(&mut self.transactions, record) {
-                    Ok(_) => {}
-                    // if the transaction has already been processed, we don't process it
-                    Err(_) => return,
-                }
+                // if the transaction has already been processed, we don't process it
+                record_transaction_if_new(&mut self.transactions, record)?;
 
                 summary.available_amount += amount;
+
+                Ok(Applied::Deposited)
+            }
+            TxRecord::WITHDRAWAL(ref metadata, amount) => {
+                if amount < dec!(0) {
+                    return Err(TxRejection::NegativeAmount);
+                }
+
+                let fee = self.config.fees.fee(&record);
+
+                // if the transaction has already been processed, we don't process it
+                if self.transactions.contains(metadata.tx_id)? {
+                    return Err(TxRejection::DuplicateTransaction);
+                }
+
+                if amount + fee > summary.headroom() {
+                    return decline(&mut self.transactions, record, self.config.strict);
+                }
+
+                record_transaction_if_new(&mut self.transactions, record)?;
+
+                summary.available_amount -= amount;
+
+                Ok(Applied::Withdrawn)
             }
-            TxRecord::WITHDRAWAL(_, amount) => {
+            TxRecord::TRANSFER(ref metadata, destination, amount) => {
                 if amount < dec!(0) {
-                    return;
+                    return Err(TxRejection::NegativeAmount);
                 }
 
-                match record_transaction_if_new(&mut self.transactions, record) {
-                    Ok(_) => {}
-                    // if the transaction has already been processed, we don't process it
-                    Err(_) => return,
+                if destination == metadata.client_id {
+                    return Err(TxRejection::SelfTransfer);
                 }
 
-                if amount <= summary.available_amount {
-                    summary.available_amount -= amount;
+                if destination_locked {
+                    return Err(TxRejection::DestinationLocked);
                 }
+
+                if self.transactions.contains(metadata.tx_id)? {
+                    return Err(TxRejection::DuplicateTransaction);
+                }
+
+                if amount > summary.headroom() {
+                    return decline(&mut self.transactions, record, self.config.strict);
+                }
+
+                record_transaction_if_new(&mut self.transactions, record)?;
+
+                summary.available_amount -= amount;
+
+                let destination_key = (destination, key.1);
+                self.account_summaries
+                    .entry(destination_key)
+                    .or_insert_with(|| new_account(&self.credit_limits, destination_key))
+                    .available_amount += amount;
+
+                Ok(Applied::Transferred)
+            }
+            TxRecord::AUTHORIZE(ref metadata, amount) => {
+                if amount < dec!(0) {
+                    return Err(TxRejection::NegativeAmount);
+                }
+
+                if self.transactions.contains(metadata.tx_id)? {
+                    return Err(TxRejection::DuplicateTransaction);
+                }
+
+                // the authorization also covers the withdrawal fee of its capture,
+                // the house account is not charged
+                let capture_fee = match metadata.client_id == self.config.fees.house_client {
+                    true => dec!(0),
+                    false => self.config.fees.withdrawal_fee(amount),
+                };
+
+                if amount + capture_fee > summary.headroom() {
+                    return decline(&mut self.transactions, record, self.config.strict);
+                }
+
+                record_transaction_if_new(&mut self.transactions, record)?;
+
+                summary.available_amount -= amount;
+                summary.held_amount += amount;
+                summary.authorized_amount += amount;
+
+                Ok(Applied::Authorized)
+            }
+            TxRecord::CAPTURE(capture_metadata) => {
+                let (amount, tx_record_flags) =
+                    pending_authorization(&self.transactions, &capture_metadata)?;
+
+                self.transactions
+                    .update_flags(capture_metadata.tx_id, tx_record_flags.with_captured())?;
+
+                summary.held_amount -= amount;
+                summary.authorized_amount -= amount;
+
+                Ok(Applied::Captured)
+            }
+            TxRecord::VOID(void_metadata) => {
+                let (amount, tx_record_flags) =
+                    pending_authorization(&self.transactions, &void_metadata)?;
+
+                self.transactions
+                    .update_flags(void_metadata.tx_id, tx_record_flags.with_voided())?;
+
+                summary.held_amount -= amount;
+                summary.authorized_amount -= amount;
+                summary.available_amount += amount;
+
+                Ok(Applied::Voided)
             }
             TxRecord::DISPUTE(dispute_metadata) => {
-                if let Some((TxRecord::DEPOSIT(deposit_metadata, amount), tx_record_flags)) =
-                    self.transactions.get_mut(&dispute_metadata.tx_id)
-                {
-                    if deposit_metadata.client_id != dispute_metadata.client_id {
-                        return;
-                    }
+                let (disputed, mut tx_record_flags) = disputed_transaction(
+                    &self.transactions,
+                    &dispute_metadata,
+                    self.config.withdrawal_dispute_policy,
+                )?;
+
+                if tx_record_flags.chargedback {
+                    return Err(TxRejection::AlreadyChargedBack);
+                }
+
+                if tx_record_flags.disputed {
+                    return Err(TxRejection::AlreadyDisputed);
+                }
 
-                    if tx_record_flags.disputed || tx_record_flags.chargedback {
-                        return;
+                // part of a disputed deposit that is not held
+                let shortfall = match (disputed, self.config.deposit_dispute_policy) {
+                    (
+                        DisputedTransaction::Deposit(amount)
+                        | DisputedTransaction::Transfer(amount, _),
+                        DepositDisputePolicy::Reject,
+                    ) if amount > summary.available_amount => {
+                        return Err(TxRejection::DisputeExceedsAvailable);
                     }
+                    (
+                        DisputedTransaction::Deposit(amount)
+                        | DisputedTransaction::Transfer(amount, _),
+                        DepositDisputePolicy::HoldAvailable,
+                    ) => (amount - summary.available_amount.max(dec!(0))).max(dec!(0)),
+                    _ => dec!(0),
+                };
 
-                    tx_record_flags.disputed = true;
+                tx_record_flags.disputed = true;
+                tx_record_flags.shortfall = shortfall;
+                if let DisputedTransaction::Withdrawal(_, policy) = disputed {
+                    tx_record_flags.withdrawal_dispute_policy = Some(policy);
+                }
+                self.transactions
+                    .update_flags(dispute_metadata.tx_id, tx_record_flags)?;
 
-                    summary.available_amount -= *amount;
-                    summary.held_amount += *amount;
+                match disputed {
+                    DisputedTransaction::Deposit(amount)
+                    | DisputedTransaction::Transfer(amount, _) => {
+                        summary.available_amount -= amount - shortfall;
+                        summary.held_amount += amount - shortfall;
+                        summary.shortfall += shortfall;
+                    }
+                    DisputedTransaction::Withdrawal(amount, policy) => match policy {
+                        WithdrawalDisputePolicy::Reject => unreachable!(),
+                        WithdrawalDisputePolicy::HoldReversal => summary.held_amount += amount,
+                        WithdrawalDisputePolicy::ProvisionalCredit => {
+                            summary.available_amount += amount
+                        }
+                    },
                 }
+
+                Ok(Applied::Disputed)
             }
             TxRecord::RESOLVE(resolve_metadata) => {
-                if let Some((TxRecord::DEPOSIT(deposit_metadata, amount), tx_record_flags)) =
-                    self.transactions.get_mut(&resolve_metadata.tx_id)
-                {
-                    if deposit_metadata.client_id != resolve_metadata.client_id {
-                        return;
-                    }
+                let (disputed, mut tx_record_flags) = disputed_transaction(
+                    &self.transactions,
+                    &resolve_metadata,
+                    self.config.withdrawal_dispute_policy,
+                )?;
 
-                    if !tx_record_flags.disputed || tx_record_flags.chargedback {
-                        return;
-                    }
+                if tx_record_flags.chargedback {
+                    return Err(TxRejection::AlreadyChargedBack);
+                }
+
+                if !tx_record_flags.disputed {
+                    return Err(TxRejection::NotDisputed);
+                }
 
-                    tx_record_flags.disputed = false;
+                let shortfall = tx_record_flags.shortfall;
 
-                    summary.available_amount += *amount;
-                    summary.held_amount -= *amount;
+                tx_record_flags.disputed = false;
+                tx_record_flags.shortfall = dec!(0);
+                tx_record_flags.withdrawal_dispute_policy = None;
+                self.transactions
+                    .update_flags(resolve_metadata.tx_id, tx_record_flags)?;
+
+                match disputed {
+                    DisputedTransaction::Deposit(amount)
+                    | DisputedTransaction::Transfer(amount, _) => {
+                        summary.available_amount += amount - shortfall;
+                        summary.held_amount -= amount - shortfall;
+                        summary.shortfall -= shortfall;
+                    }
+                    DisputedTransaction::Withdrawal(amount, policy) => match policy {
+                        WithdrawalDisputePolicy::Reject => unreachable!(),
+                        WithdrawalDisputePolicy::HoldReversal => summary.held_amount -= amount,
+                        WithdrawalDisputePolicy::ProvisionalCredit => {
+                            summary.available_amount -= amount
+                        }
+                    },
                 }
+
+                Ok(Applied::Resolved)
             }
             TxRecord::CHARGEBACK(chargeback_metadata) => {
-                if let Some((TxRecord::DEPOSIT(deposit_metadata, amount), tx_record_flags)) =
-                    self.transactions.get_mut(&chargeback_metadata.tx_id)
-                {
-                    if deposit_metadata.client_id != chargeback_metadata.client_id {
-                        return;
-                    }
+                let (disputed, mut tx_record_flags) = disputed_transaction(
+                    &self.transactions,
+                    &chargeback_metadata,
+                    self.config.withdrawal_dispute_policy,
+                )?;
+
+                if tx_record_flags.chargedback {
+                    return Err(TxRejection::AlreadyChargedBack);
+                }
+
+                if !tx_record_flags.disputed {
+                    return Err(TxRejection::NotDisputed);
+                }
 
-                    if !tx_record_flags.disputed || tx_record_flags.chargedback {
-                        return;
+                let shortfall = tx_record_flags.shortfall;
+
+                tx_record_flags.disputed = false;
+                tx_record_flags.chargedback = true;
+                tx_record_flags.shortfall = dec!(0);
+                tx_record_flags.withdrawal_dispute_policy = None;
+                self.transactions
+                    .update_flags(chargeback_metadata.tx_id, tx_record_flags)?;
+
+                match disputed {
+                    DisputedTransaction::Deposit(amount)
+                    | DisputedTransaction::Transfer(amount, _) => {
+                        summary.held_amount -= amount - shortfall;
+                        summary.available_amount -= shortfall;
+                        summary.shortfall -= shortfall;
                     }
+                    DisputedTransaction::Withdrawal(amount, policy) => match policy {
+                        WithdrawalDisputePolicy::Reject => unreachable!(),
+                        WithdrawalDisputePolicy::HoldReversal => {
+                            summary.held_amount -= amount;
+                            summary.available_amount += amount;
+                        }
+                        // the provisional credit is already in available
+                        WithdrawalDisputePolicy::ProvisionalCredit => {}
+                    },
+                }
 
-                    tx_record_flags.disputed = false;
-                    tx_record_flags.chargedback = true;
+                summary.locked = true;
 
-                    summary.held_amount -= *amount;
-                    summary.locked = true;
+                // the transferred amount goes back to the source account, even if locked
+                if let DisputedTransaction::Transfer(amount, source_key) = disputed {
+                    self.account_summaries
+                        .entry(source_key)
+                        .or_insert_with(|| new_account(&self.credit_limits, source_key))
+                        .available_amount += amount;
                 }
+
+                Ok(Applied::ChargedBack)
             }
+            TxRecord::ADJUSTMENT(_, bucket, amount, _) => {
+                // recorded so that the transaction id can not be reused, nor disputed
+                record_transaction_if_new(&mut self.transactions, record)?;
+
+                match bucket {
+                    Bucket::Available => summary.available_amount += amount,
+                    Bucket::Held => summary.held_amount += amount,
+                }
+                summary.adjustments += 1;
+                summary.adjusted_amount += amount;
+
+                Ok(Applied::Adjusted)
+            }
+            TxRecord::UNLOCK(..) => unreachable!("applied before the locked check"),
         }
     }
 
+    /// account of the client in the default currency
     pub fn account_summary(&self, client_id: u16) -> Option<&AccountSummary> {
-        self.account_summaries.get(&client_id)
+        self.account_summary_in(client_id, Currency::default())
     }
 
-    pub fn summary_iterator(&self) -> impl Iterator<Item = (&u16, &AccountSummary)> {
-        self.account_summaries.iter()
+    pub fn account_summary_in(
+        &self,
+        client_id: u16,
+        currency: Currency,
+    ) -> Option<&AccountSummary> {
+        self.account_summaries.get(&(client_id, currency))
+    }
+
+    /// account summaries in ascending client id order, then currency order,
+    /// a client has one account per currency
+    pub fn summary_iterator(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = (&u16, &AccountSummary)> + ExactSizeIterator {
+        self.account_summaries
+            .iter()
+            .map(|((client_id, _), summary)| (client_id, summary))
+    }
+
+    /// whether an adjustment has been applied to an account
+    pub fn has_adjustments(&self) -> bool {
+        self.account_summaries
+            .values()
+            .any(|summary| summary.adjustments > 0)
+    }
+
+    /// whether an authorization is pending, see [`AccountSummary::authorized_amount`]
+    pub fn has_authorizations(&self) -> bool {
+        self.account_summaries
+            .values()
+            .any(|summary| !summary.authorized_amount.is_zero())
+    }
+
+    /// whether fees can be charged, or have been charged, e.g. before a snapshot
+    pub fn has_fees(&self) -> bool {
+        self.config.fees.charges_fees()
+            || self
+                .account_summaries
+                .values()
+                .any(|summary| !summary.fees.is_zero())
+    }
+
+    /// whether a credit limit has been set, even to zero
+    pub fn has_credit_limits(&self) -> bool {
+        !self.credit_limits.is_empty()
+    }
+
+    /// whether an account is in another currency than the default one
+    pub fn has_currencies(&self) -> bool {
+        self.account_summaries
+            .keys()
+            .any(|(_, currency)| !currency.is_default())
     }
 }
 
+// accounts are kept per client and per currency
+type AccountKey = (u16, Currency);
+
+// summary of an account that is not opened yet, with its credit limit if any
+fn new_account(credit_limits: &HashMap<AccountKey, Decimal>, key: AccountKey) -> AccountSummary {
+    let credit_limit = credit_limits.get(&key).copied().unwrap_or_default();
+    AccountSummary::new(key.1, credit_limit)
+}
+
+#[derive(Clone, Copy)]
+enum DisputedTransaction {
+    Deposit(Decimal),
+    Withdrawal(Decimal, WithdrawalDisputePolicy),
+    // disputed by the destination like a deposit, with the account of the source
+    Transfer(Decimal, AccountKey),
+}
+
+// client that can dispute the transaction: the destination of a transfer, the client otherwise
+fn disputing_client(record: &TxRecord) -> u16 {
+    match record {
+        TxRecord::TRANSFER(_, destination, _) => *destination,
+        _ => record.record_metadata().client_id,
+    }
+}
+
+// looks up the transaction targeted by a dispute, a resolve or a chargeback
+fn disputed_transaction(
+    transactions: &impl TransactionStore,
+    metadata: &TxRecordMetadata,
+    withdrawal_dispute_policy: WithdrawalDisputePolicy,
+) -> Result<(DisputedTransaction, TxRecordFlags), TxRejection> {
+    let (record, tx_record_flags) = transactions
+        .get(metadata.tx_id)?
+        .ok_or(TxRejection::UnknownTransaction)?;
+
+    let disputed = match record {
+        TxRecord::DEPOSIT(_, amount) => DisputedTransaction::Deposit(amount),
+        TxRecord::TRANSFER(ref source, _, amount) => {
+            DisputedTransaction::Transfer(amount, (source.client_id, source.currency))
+        }
+        TxRecord::WITHDRAWAL(_, amount) => {
+            // an open dispute keeps the policy it was opened with
+            match tx_record_flags
+                .withdrawal_dispute_policy
+                .unwrap_or(withdrawal_dispute_policy)
+            {
+                WithdrawalDisputePolicy::Reject => return Err(TxRejection::NotDisputable),
+                policy => DisputedTransaction::Withdrawal(amount, policy),
+            }
+        }
+        _ => return Err(TxRejection::NotDisputable),
+    };
+
+    if disputing_client(&record) != metadata.client_id {
+        return Err(TxRejection::ClientMismatch);
+    }
+
+    if tx_record_flags.declined {
+        return Err(TxRejection::NotDisputable);
+    }
+
+    Ok((disputed, tx_record_flags))
+}
+
+// looks up the authorization targeted by a capture or a void, with its amount
+fn pending_authorization(
+    transactions: &impl TransactionStore,
+    metadata: &TxRecordMetadata,
+) -> Result<(Decimal, TxRecordFlags), TxRejection> {
+    let (record, tx_record_flags) = transactions
+        .get(metadata.tx_id)?
+        .ok_or(TxRejection::UnknownTransaction)?;
+
+    let amount = match record {
+        TxRecord::AUTHORIZE(_, amount) => amount,
+        _ => return Err(TxRejection::NotAnAuthorization),
+    };
+
+    if record.record_metadata().client_id != metadata.client_id {
+        return Err(TxRejection::ClientMismatch);
+    }
+
+    // a declined authorization never held its amount
+    if tx_record_flags.captured || tx_record_flags.voided || tx_record_flags.declined {
+        return Err(TxRejection::AuthorizationClosed);
+    }
+
+    Ok((amount, tx_record_flags))
+}
+
+/// Effect of a record accepted by [`TxProc::submit_tx_record`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Applied {
+    Deposited,
+    Withdrawn,
+    Transferred,
+    Disputed,
+    Resolved,
+    ChargedBack,
+    Authorized,
+    Captured,
+    Voided,
+    Unlocked,
+    Adjusted,
+}
+
+/// Reason why a record was rejected by [`TxProc::submit_tx_record`]
+///
+/// a rejected record leaves the balances untouched, but it may open the account of its client,
+/// journaled as [`JournalEvent::AccountOpened`], and a withdrawal, a transfer or an authorization
+/// declined for insufficient funds keeps its transaction id, see [`TxRecordFlags::is_declined`],
+/// in strict mode a rejected record changes nothing, see [`TxProcConfig::strict`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxRejection {
+    /// the transaction store failed to read or write the transaction
+    Storage(io::ErrorKind),
+    /// the client account has been locked by a chargeback
+    AccountLocked,
+    /// negative amount in a deposit or a withdrawal
+    NegativeAmount,
+    /// the transaction id has already been processed
+    DuplicateTransaction,
+    /// the withdrawal or transfer amount exceeds the available amount
+    InsufficientFunds,
+    /// the transfer destination is the source client
+    SelfTransfer,
+    /// the transfer destination account has been locked by a chargeback
+    DestinationLocked,
+    /// the dispute/resolve/chargeback/capture/void targets a transaction that does not exist
+    UnknownTransaction,
+    /// the dispute/resolve/chargeback/capture/void targets a transaction of another client
+    ClientMismatch,
+    /// the dispute/resolve/chargeback targets a transaction that can not be disputed
+    NotDisputable,
+    /// the transaction is already under dispute
+    AlreadyDisputed,
+    /// the disputed deposit exceeds the available amount, see [`DepositDisputePolicy::Reject`]
+    DisputeExceedsAvailable,
+    /// the resolve/chargeback targets a transaction that is not under dispute
+    NotDisputed,
+    /// the transaction has already been charged back
+    AlreadyChargedBack,
+    /// the capture/void targets a transaction that is not an authorization
+    NotAnAuthorization,
+    /// the authorization has already been captured or voided
+    AuthorizationClosed,
+    /// the unlock targets an account that is not locked, or not opened
+    NotLocked,
+    /// a previous record has been rejected in strict mode, see [`TxProcConfig::strict`]
+    Halted,
+}
+
+impl TxRejection {
+    /// stable, machine-readable identifier of the rejection reason
+    pub fn code(&self) -> &'static str {
+        match self {
+            TxRejection::Storage(_) => "storage_error",
+            TxRejection::AccountLocked => "account_locked",
+            TxRejection::NegativeAmount => "negative_amount",
+            TxRejection::DuplicateTransaction => "duplicate_transaction",
+            TxRejection::InsufficientFunds => "insufficient_funds",
+            TxRejection::SelfTransfer => "self_transfer",
+            TxRejection::DestinationLocked => "destination_locked",
+            TxRejection::UnknownTransaction => "unknown_transaction",
+            TxRejection::ClientMismatch => "client_mismatch",
+            TxRejection::NotDisputable => "not_disputable",
+            TxRejection::AlreadyDisputed => "already_disputed",
+            TxRejection::DisputeExceedsAvailable => "dispute_exceeds_available",
+            TxRejection::NotDisputed => "not_disputed",
+            TxRejection::AlreadyChargedBack => "already_charged_back",
+            TxRejection::NotAnAuthorization => "not_an_authorization",
+            TxRejection::AuthorizationClosed => "authorization_closed",
+            TxRejection::NotLocked => "not_locked",
+            TxRejection::Halted => "halted",
+        }
+    }
+}
+
+impl fmt::Display for TxRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TxRejection::Storage(kind) => return write!(f, "transaction store failure: {}", kind),
+            TxRejection::AccountLocked => "account is locked",
+            TxRejection::NegativeAmount => "amount is negative",
+            TxRejection::DuplicateTransaction => "transaction has already been processed",
+            TxRejection::InsufficientFunds => "insufficient available funds",
+            TxRejection::SelfTransfer => "transfer destination is the source client",
+            TxRejection::DestinationLocked => "transfer destination account is locked",
+            TxRejection::UnknownTransaction => "referenced transaction does not exist",
+            TxRejection::ClientMismatch => "referenced transaction belongs to another client",
+            TxRejection::NotDisputable => "referenced transaction can not be disputed",
+            TxRejection::AlreadyDisputed => "transaction is already disputed",
+            TxRejection::DisputeExceedsAvailable => "disputed amount exceeds the available funds",
+            TxRejection::NotDisputed => "transaction is not disputed",
+            TxRejection::AlreadyChargedBack => "transaction has already been charged back",
+            TxRejection::NotAnAuthorization => "referenced transaction is not an authorization",
+            TxRejection::AuthorizationClosed => "authorization has already been captured or voided",
+            TxRejection::NotLocked => "account is not locked",
+            TxRejection::Halted => "processing halted by a previous rejection",
+        })
+    }
+}
+
+impl Error for TxRejection {}
+
+impl From<io::Error> for TxRejection {
+    fn from(e: io::Error) -> TxRejection {
+        TxRejection::Storage(e.kind())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TxRecord {
     DEPOSIT(TxRecordMetadata, Decimal),
     WITHDRAWAL(TxRecordMetadata, Decimal),
+    /// moves the amount from the client account to the destination client account in the same currency
+    ///
+    /// the destination can dispute the transfer like a deposit,
+    /// a chargeback then credits the amount back to the source account
+    TRANSFER(TxRecordMetadata, u16, Decimal),
     DISPUTE(TxRecordMetadata),
     RESOLVE(TxRecordMetadata),
     CHARGEBACK(TxRecordMetadata),
+    /// reserves the amount of the client account, moved from available to held,
+    /// under the transaction id, referenced by the capture or the void of the authorization
+    ///
+    /// an authorization can not be disputed
+    AUTHORIZE(TxRecordMetadata, Decimal),
+    /// settles the whole authorized amount as a withdrawal, removing it from held
+    CAPTURE(TxRecordMetadata),
+    /// releases the authorized amount from held back to available
+    VOID(TxRecordMetadata),
+    /// admin record reactivating the client account locked by a chargeback, with the reason
+    ///
+    /// the transaction id identifies the record in the journal, it is not recorded
+    UNLOCK(TxRecordMetadata, String),
+    /// admin record correcting a balance of the client account by a signed amount, with the reason
+    ///
+    /// it also applies to locked accounts, and can not be disputed
+    ADJUSTMENT(TxRecordMetadata, Bucket, Decimal, String),
+}
+
+/// Balance of an account targeted by a [`TxRecord::ADJUSTMENT`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Bucket {
+    #[default]
+    Available,
+    Held,
+}
+
+impl FromStr for Bucket {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Bucket, String> {
+        match s {
+            "available" => Ok(Bucket::Available),
+            "held" => Ok(Bucket::Held),
+            _ => Err(format!("unknown bucket: {}", s)),
+        }
+    }
 }
 
 impl TxRecord {
@@ -177,20 +1438,125 @@ impl TxRecord {
         match self {
             TxRecord::DEPOSIT(metadata, _) => metadata,
             TxRecord::WITHDRAWAL(metadata, _) => metadata,
+            TxRecord::TRANSFER(metadata, _, _) => metadata,
             TxRecord::DISPUTE(metadata) => metadata,
             TxRecord::RESOLVE(metadata) => metadata,
             TxRecord::CHARGEBACK(metadata) => metadata,
+            TxRecord::AUTHORIZE(metadata, _) => metadata,
+            TxRecord::CAPTURE(metadata) => metadata,
+            TxRecord::VOID(metadata) => metadata,
+            TxRecord::UNLOCK(metadata, _) => metadata,
+            TxRecord::ADJUSTMENT(metadata, _, _, _) => metadata,
         }
     }
+
+    /// whether the record is an admin record, such as [`TxRecord::UNLOCK`],
+    /// that end-user inputs should not contain
+    pub fn is_admin(&self) -> bool {
+        matches!(self, TxRecord::UNLOCK(..) | TxRecord::ADJUSTMENT(..))
+    }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TxRecordMetadata {
     client_id: u16,
     tx_id: u32,
+    currency: Currency,
 }
 
 impl TxRecordMetadata {
+    /// metadata of a record in the default currency
     pub fn new(client_id: u16, tx_id: u32) -> TxRecordMetadata {
-        TxRecordMetadata { client_id, tx_id }
+        TxRecordMetadata {
+            client_id,
+            tx_id,
+            currency: Currency::default(),
+        }
+    }
+
+    /// currency of a deposit or a withdrawal, disputes, resolves and chargebacks
+    /// apply to the currency of the transaction they reference
+    pub fn with_currency(mut self, currency: Currency) -> TxRecordMetadata {
+        self.currency = currency;
+        self
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    pub fn client_id(&self) -> u16 {
+        self.client_id
+    }
+
+    pub fn tx_id(&self) -> u32 {
+        self.tx_id
+    }
+}
+
+/// Three-letter currency code, e.g. `EUR`
+///
+/// records without currency are in the default currency, whose code is empty
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    /// parses a code of three ASCII letters, in any case, the empty code is the default currency
+    pub fn new(code: &str) -> Option<Currency> {
+        if code.is_empty() {
+            return Some(Currency::default());
+        }
+        match code.as_bytes() {
+            &[a, b, c] if code.bytes().all(|b| b.is_ascii_alphabetic()) => Some(Currency([
+                a.to_ascii_uppercase(),
+                b.to_ascii_uppercase(),
+                c.to_ascii_uppercase(),
+            ])),
+            _ => None,
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Currency::default()
+    }
+
+    /// upper case code, empty for the default currency
+    pub fn code(&self) -> &str {
+        match self.is_default() {
+            true => "",
+            // only ASCII letters are accepted by `Currency::new`
+            false => std::str::from_utf8(&self.0).unwrap_or_default(),
+        }
+    }
+
+    pub(crate) fn to_bytes(self) -> [u8; 3] {
+        self.0
+    }
+
+    pub(crate) fn from_bytes(bytes: [u8; 3]) -> Option<Currency> {
+        match bytes {
+            [0, 0, 0] => Some(Currency::default()),
+            _ => Currency::new(std::str::from_utf8(&bytes).ok()?),
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Currency, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        Currency::new(&code)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid currency: {}", code)))
     }
 }
@@ -0,0 +1,92 @@
+use crate::store::TransactionStore;
+use crate::{Currency, TxProc};
+use ::csv::{ReaderBuilder, Trim};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+
+/// Credit limit of a client account, see [`TxProc::set_credit_limit`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct CreditLimit {
+    pub client: u16,
+    /// optional column, the default currency if missing or empty
+    #[serde(default)]
+    pub currency: Currency,
+    pub limit: Decimal,
+}
+
+/// reads a `client, limit, currency` CSV file with a header line, the currency column is optional
+///
+/// unlike the records, an invalid line fails the whole file
+pub fn read_credit_limits(reader: impl Read) -> Result<Vec<CreditLimit>, CreditLimitError> {
+    let mut reader = ReaderBuilder::new().trim(Trim::All).from_reader(reader);
+    let headers = reader
+        .headers()
+        .map_err(CreditLimitError::from_csv)?
+        .clone();
+
+    let mut limits = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(CreditLimitError::from_csv)?;
+        let limit: CreditLimit =
+            record
+                .deserialize(Some(&headers))
+                .map_err(|e| CreditLimitError {
+                    line: record.position().map(|p| p.line()),
+                    message: e.to_string(),
+                })?;
+        if limit.limit < dec!(0) {
+            return Err(CreditLimitError {
+                line: record.position().map(|p| p.line()),
+                message: format!("negative limit for client {}", limit.client),
+            });
+        }
+        limits.push(limit);
+    }
+
+    Ok(limits)
+}
+
+impl<S: TransactionStore> TxProc<S> {
+    /// sets the credit limits read by [`read_credit_limits`]
+    pub fn set_credit_limits(&mut self, limits: impl IntoIterator<Item = CreditLimit>) {
+        for limit in limits {
+            self.set_credit_limit(limit.client, limit.currency, limit.limit);
+        }
+    }
+}
+
+/// A credit limits file that can not be read
+#[derive(Debug)]
+pub struct CreditLimitError {
+    line: Option<u64>,
+    message: String,
+}
+
+impl CreditLimitError {
+    fn from_csv(e: ::csv::Error) -> CreditLimitError {
+        CreditLimitError {
+            line: e.position().map(|p| p.line()),
+            message: e.to_string(),
+        }
+    }
+
+    /// line number in the file, starting at 1
+    pub fn line(&self) -> Option<u64> {
+        self.line
+    }
+}
+
+impl fmt::Display for CreditLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "invalid credit limit on line {}: {}", line, self.message),
+            None => write!(f, "invalid credit limits: {}", self.message),
+        }
+    }
+}
+
+impl Error for CreditLimitError {}
@@ -1,113 +1,1036 @@
-use csv::{ReaderBuilder, Trim};
-use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
-use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::{self};
+use std::io::{self, BufWriter};
+use std::net::TcpListener;
+use std::process::ExitCode;
 
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rust_decimal::Decimal;
+use tx_proc::csv::{AccountSummaryWriter, CsvOptions, TxRecordReader};
+use tx_proc::journal::JournalWriter;
+use tx_proc::json::{AccountSummaryJsonWriter, TxRecordJsonReader};
+use tx_proc::limits::{self, CreditLimit};
+use tx_proc::rejects::{RejectFormat, RejectWriter};
+use tx_proc::scale::{InputScale, OutputScale, Rounding, MAX_PLACES};
+use tx_proc::server::SummaryColumns;
+use tx_proc::sharded::ShardedTxProc;
+use tx_proc::store::{AppendLogStore, InMemoryStore, TransactionStore};
 use tx_proc::*;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+// exit codes, following sysexits.h
+const EXIT_FAILURE: u8 = 1;
+const EXIT_USAGE: u8 = 64;
+const EXIT_SKIPPED_RECORDS: u8 = 65;
+const EXIT_IO: u8 = 74;
+
+const EXIT_CODES_HELP: &str = "Exit codes:
+  0   every record was processed
+  1   other failure, e.g. invalid state file
+  64  usage error
+  65  completed, but some records were skipped, see --rejects,
+      or stopped at the first skipped record with --strict
+  74  I/O error: input file not found, output not writable, ..";
+
+/// Processes deposits, withdrawals, disputes, resolves and chargebacks into account summaries
+///
+/// `tx_proc [OPTIONS] [FILES]..` is a shortcut for `tx_proc process [OPTIONS] [FILES]..`
+#[derive(Parser)]
+#[command(
+    name = "tx_proc",
+    version,
+    args_conflicts_with_subcommands = true,
+    after_help = EXIT_CODES_HELP
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    process: ProcessArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Processes the records and writes the account summaries to stdout
+    #[command(after_help = EXIT_CODES_HELP)]
+    Process(ProcessArgs),
+    /// Processes the records without writing the account summaries, only reports skipped records
+    #[command(after_help = EXIT_CODES_HELP)]
+    Validate(InputArgs),
+    /// Writes the account summaries of a state file saved with --state-out
+    #[command(after_help = EXIT_CODES_HELP)]
+    Report(ReportArgs),
+    /// Listens on a TCP address for CSV streams, see the README for the protocol
+    Serve {
+        /// e.g. 127.0.0.1:7878
+        address: String,
+
+        /// Adds a `currency` column to the summaries, after `client`, empty for the default currency
+        #[arg(long)]
+        currency_column: bool,
+
+        /// Adds an `authorized` column to the summaries, the part of `held` reserved by authorizations
+        #[arg(long)]
+        authorized_column: bool,
+    },
+}
+
+#[derive(Args)]
+struct InputArgs {
+    /// Input files, processed in order, no file or `-` reads from stdin
+    files: Vec<String>,
+
+    #[arg(long, value_enum, default_value_t = InputFormat::Csv)]
+    input_format: InputFormat,
+
+    /// The CSV input has no header line
+    #[arg(long)]
+    no_headers: bool,
+
+    /// CSV column names in file order, e.g. tx,client,type,amount, replaces the header line if any
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    columns: Option<Vec<String>>,
+
+    /// CSV field delimiter, a single character or `tab`
+    #[arg(long, value_name = "CHAR", default_value = ",", value_parser = parse_delimiter)]
+    delimiter: u8,
+
+    /// Skips the records whose amount has more decimal places, unless --input-rounding is given
+    #[arg(long, value_name = "PLACES", value_parser = parse_places)]
+    input_scale: Option<u32>,
+
+    /// Rounds the amounts with more decimal places than --input-scale instead of skipping them
+    #[arg(
+        long,
+        value_name = "half-even|half-up|down|up",
+        requires = "input_scale"
+    )]
+    input_rounding: Option<Rounding>,
+
+    /// Reads the admin records, such as `unlock`, from this file only, processed after the
+    /// input files, whose admin records are then skipped
+    #[arg(long, value_name = "FILE")]
+    admin_input: Option<String>,
+
+    /// Writes one line per skipped record to this file
+    #[arg(long, value_name = "FILE")]
+    rejects: Option<String>,
+
+    #[arg(long, value_name = "csv|jsonl", default_value = "csv")]
+    rejects_format: RejectFormat,
+
+    /// Starts from a state file saved with --state-out
+    #[arg(long, value_name = "FILE")]
+    state_in: Option<String>,
+
+    /// Allows withdrawals to take the available amount negative, down to the limits of a
+    /// `client,limit,currency` CSV file, the limits are not part of the state
+    #[arg(long, value_name = "FILE")]
+    credit_limits: Option<String>,
+
+    /// What a dispute on a deposit does when the available amount does not cover it:
+    /// take the available amount negative, skip the dispute, or hold only the available amount
+    /// and record the rest as a shortfall
+    #[arg(
+        long,
+        value_name = "allow-negative|reject|hold-available",
+        default_value = "allow-negative"
+    )]
+    deposit_dispute_policy: DepositDisputePolicy,
+
+    /// Flat fee charged on every withdrawal, the withdrawal is skipped when the available amount
+    /// does not cover the amount and the fee
+    #[arg(long, value_name = "AMOUNT", default_value = "0", value_parser = parse_fee)]
+    withdrawal_fee: Decimal,
+
+    /// Part of the withdrawn amount added to the withdrawal fee, e.g. 0.01 for 1%
+    #[arg(long, value_name = "RATE", default_value = "0", value_parser = parse_fee)]
+    withdrawal_fee_rate: Decimal,
+
+    /// Fee charged to the disputing client on every applied dispute
+    #[arg(long, value_name = "AMOUNT", default_value = "0", value_parser = parse_fee)]
+    dispute_fee: Decimal,
+
+    /// Fee charged on every applied chargeback
+    #[arg(long, value_name = "AMOUNT", default_value = "0", value_parser = parse_fee)]
+    chargeback_fee: Decimal,
+
+    /// Client id of the house account credited with the fees, its own records are free
+    #[arg(long, value_name = "CLIENT", default_value_t = 0)]
+    fee_account: u16,
 
-    if args.len() != 2 {
-        panic!("invalid argument count {}", args.len())
+    /// Processes the records on this many threads, sharded by client
+    #[arg(long, value_name = "COUNT", default_value_t = 1)]
+    workers: usize,
+
+    /// Stops at the first skipped record, without writing the account summaries nor the state,
+    /// not supported with --workers
+    #[arg(long)]
+    strict: bool,
+}
+
+#[derive(Args)]
+struct ProcessArgs {
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    /// Keeps the transactions in this append-only file instead of memory, the file is truncated first
+    #[arg(long, value_name = "FILE")]
+    tx_log: Option<String>,
+
+    /// Saves the state to this file once every record is processed
+    #[arg(long, value_name = "FILE")]
+    state_out: Option<String>,
+
+    /// Writes the applied state transitions to this file, not supported with --workers
+    #[arg(long, value_name = "FILE")]
+    journal: Option<String>,
+}
+
+#[derive(Args)]
+struct ReportArgs {
+    /// State file saved with --state-out
+    state: String,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Args)]
+struct OutputArgs {
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv)]
+    output_format: OutputFormat,
+
+    /// Order of the account summaries
+    #[arg(long, value_enum, default_value_t = SummaryOrder::Client)]
+    order: SummaryOrder,
+
+    /// Writes the amounts with exactly this many decimal places
+    #[arg(long, value_name = "PLACES", value_parser = parse_places)]
+    output_scale: Option<u32>,
+
+    /// Rounding of the amounts with more decimal places than --output-scale
+    #[arg(
+        long,
+        value_name = "half-even|half-up|down|up",
+        default_value = "half-even",
+        requires = "output_scale"
+    )]
+    output_rounding: Rounding,
+
+    /// Adds `shortfall` and `negative` columns, marking the accounts in a negative position
+    #[arg(long)]
+    negative_flag: bool,
+
+    /// Adds a `currency` column after `client`, empty for the default currency
+    #[arg(long)]
+    currency_column: bool,
+
+    /// Adds `adjustments` and `adjusted` columns, the number and the net amount of the adjustments
+    #[arg(long)]
+    adjustment_columns: bool,
+
+    /// Adds an `authorized` column, the part of `held` reserved by authorizations
+    #[arg(long)]
+    authorized_column: bool,
+}
+
+fn parse_delimiter(delimiter: &str) -> Result<u8, String> {
+    match delimiter {
+        "tab" | "\\t" => Ok(b'\t'),
+        _ if delimiter.len() == 1 && delimiter.is_ascii() => Ok(delimiter.as_bytes()[0]),
+        _ => Err("expected a single ASCII character or `tab`".to_string()),
     }
+}
 
-    let file_path = &args[1];
+fn parse_fee(fee: &str) -> Result<Decimal, String> {
+    match fee.parse::<Decimal>() {
+        Ok(fee) if fee >= Decimal::ZERO => Ok(fee),
+        _ => Err("expected a non-negative amount".to_string()),
+    }
+}
 
-    let file = File::open(file_path).expect("failed to open file");
+fn parse_places(places: &str) -> Result<u32, String> {
+    match places.parse() {
+        Ok(places) if places <= MAX_PLACES => Ok(places),
+        _ => Err(format!(
+            "expected a number of places from 0 to {}",
+            MAX_PLACES
+        )),
+    }
+}
 
-    let tx_proc = process_csv(file);
+#[derive(Clone, Copy, ValueEnum)]
+enum InputFormat {
+    Csv,
+    Jsonl,
+}
 
-    write_account_csv_to_stdout(&tx_proc);
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Csv,
+    Json,
+    Jsonl,
 }
 
-// todo if needed, it's possible to move CSV encode/decode into the library
-fn process_csv(reader: impl Read) -> TxProc {
-    #[derive(Deserialize)]
-    struct CsvLineInput {
-        r#type: String,
-        client: u16,
-        tx: u32,
-        amount: Decimal,
+#[derive(Clone, Copy, ValueEnum)]
+enum SummaryOrder {
+    /// ascending client id
+    Client,
+    /// descending client id
+    ClientDesc,
+}
+
+// error stopping the program, each kind has its own exit code
+enum Failure {
+    Usage(String),
+    Io(String, io::Error),
+    // first skipped record with --strict
+    Halted(String),
+    Other(String),
+}
+
+impl Failure {
+    fn exit_code(&self) -> u8 {
+        match self {
+            Failure::Usage(_) => EXIT_USAGE,
+            Failure::Io(..) => EXIT_IO,
+            Failure::Halted(_) => EXIT_SKIPPED_RECORDS,
+            Failure::Other(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Usage(message) | Failure::Other(message) => write!(f, "{}", message),
+            Failure::Halted(message) => write!(f, "strict mode: {}", message),
+            Failure::Io(context, e) => write!(f, "{}: {}", context, e),
+        }
     }
+}
 
-    // big files are not a problem because
-    // the csv crate uses a BufReader of size 8 * (1 << 10) bytes = 8 KiB
-    let mut rdr = ReaderBuilder::new().trim(Trim::All).from_reader(reader);
+// adds the context to an i/o error, e.g. `.map_err(io_failure("failed to open file"))`
+fn io_failure(context: impl fmt::Display) -> impl FnOnce(io::Error) -> Failure {
+    move |e| Failure::Io(context.to_string(), e)
+}
 
-    let mut tx_proc = TxProc::default();
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            // --help and --version are reported as errors by clap
+            let _ = e.print();
+            return if e.use_stderr() {
+                ExitCode::from(EXIT_USAGE)
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+    };
 
-    for record in rdr.deserialize::<CsvLineInput>() {
-        match record {
-            Ok(csv_record) => {
-                tx_proc.submit_tx_record(match csv_record.r#type.as_str() {
-                    "deposit" => TxRecord::DEPOSIT(
-                        TxRecordMetadata::new(csv_record.client, csv_record.tx),
-                        csv_record.amount,
-                    ),
-                    "withdrawal" => TxRecord::WITHDRAWAL(
-                        TxRecordMetadata::new(csv_record.client, csv_record.tx),
-                        csv_record.amount,
+    let result = match cli.command {
+        None => process(cli.process),
+        Some(Command::Process(args)) => process(args),
+        Some(Command::Validate(args)) => validate(args),
+        Some(Command::Report(args)) => report(args),
+        Some(Command::Serve {
+            address,
+            currency_column,
+            authorized_column,
+        }) => serve(
+            &address,
+            SummaryColumns {
+                currency: currency_column,
+                // the server starts without state, end users can not submit adjustments
+                adjustments: false,
+                authorizations: authorized_column,
+            },
+        ),
+    };
+
+    match result {
+        Ok(0) => ExitCode::SUCCESS,
+        Ok(skipped) => {
+            eprintln!("records skipped: {}", skipped);
+            ExitCode::from(EXIT_SKIPPED_RECORDS)
+        }
+        Err(failure) => {
+            eprintln!("{}", failure);
+            ExitCode::from(failure.exit_code())
+        }
+    }
+}
+
+// returns the number of skipped records
+fn process(args: ProcessArgs) -> Result<u64, Failure> {
+    if args.input.workers > 1 && args.journal.is_some() {
+        return Err(Failure::Usage(
+            "--journal is not supported with --workers".to_string(),
+        ));
+    }
+    check_strict(&args.input)?;
+
+    let inputs = open_inputs(&args.input)?;
+    let limits = read_credit_limits(&args.input)?;
+    let mut rejects = create_rejects(&args.input)?;
+
+    let config = TxProcConfig {
+        deposit_dispute_policy: args.input.deposit_dispute_policy,
+        fees: fee_schedule(&args.input),
+        journal: args.journal.is_some(),
+        strict: args.input.strict,
+        ..TxProcConfig::default()
+    };
+
+    let mut journal = match &args.journal {
+        Some(path) => {
+            let file = File::create(path).map_err(io_failure(format!(
+                "failed to create journal file {}",
+                path
+            )))?;
+            Some(JournalWriter::new(BufWriter::new(file)))
+        }
+        None => None,
+    };
+
+    let skipped = match &args.tx_log {
+        // transactions are kept in an append-only file instead of memory
+        Some(path) => {
+            let store = AppendLogStore::create(path).map_err(io_failure(format!(
+                "failed to create transaction log {}",
+                path
+            )))?;
+            let tx_proc = load_state(config, store, args.input.state_in.as_deref(), &limits)?;
+            run(inputs, tx_proc, &args, rejects.as_mut(), journal.as_mut())?
+        }
+        None => {
+            let tx_proc = load_state(
+                config,
+                InMemoryStore::default(),
+                args.input.state_in.as_deref(),
+                &limits,
+            )?;
+            run(inputs, tx_proc, &args, rejects.as_mut(), journal.as_mut())?
+        }
+    };
+
+    if let Some(rejects) = rejects.as_mut() {
+        rejects
+            .flush()
+            .map_err(io_failure("failed to flush the rejects file"))?;
+    }
+
+    if let Some(journal) = journal.as_mut() {
+        journal
+            .flush()
+            .map_err(io_failure("failed to flush the journal file"))?;
+    }
+
+    Ok(skipped)
+}
+
+// processes the records like `process`, without any output but the skipped records
+fn validate(args: InputArgs) -> Result<u64, Failure> {
+    check_strict(&args)?;
+
+    let inputs = open_inputs(&args)?;
+    let limits = read_credit_limits(&args)?;
+    let mut rejects = create_rejects(&args)?;
+
+    let config = TxProcConfig {
+        deposit_dispute_policy: args.deposit_dispute_policy,
+        fees: fee_schedule(&args),
+        strict: args.strict,
+        ..TxProcConfig::default()
+    };
+
+    let tx_proc = load_state(
+        config,
+        InMemoryStore::default(),
+        args.state_in.as_deref(),
+        &limits,
+    )?;
+
+    let (_, skipped) = process_inputs(
+        inputs,
+        tx_proc,
+        &args,
+        rejects.as_mut(),
+        None::<&mut JournalWriter<io::Sink>>,
+    )?;
+
+    if let Some(rejects) = rejects.as_mut() {
+        rejects
+            .flush()
+            .map_err(io_failure("failed to flush the rejects file"))?;
+    }
+
+    Ok(skipped)
+}
+
+fn report(args: ReportArgs) -> Result<u64, Failure> {
+    let tx_proc = load_state(
+        TxProcConfig::default(),
+        InMemoryStore::default(),
+        Some(&args.state),
+        &[],
+    )?;
+
+    write_summaries_to_stdout(&tx_proc, &args.output)?;
+
+    Ok(0)
+}
+
+fn serve(address: &str, columns: SummaryColumns) -> Result<u64, Failure> {
+    let listener = TcpListener::bind(address)
+        .map_err(io_failure(format!("failed to listen on {}", address)))?;
+    let local_address = listener
+        .local_addr()
+        .map_err(io_failure("failed to get the local address"))?;
+    eprintln!("listening on {}", local_address);
+
+    server::serve(listener, TxProc::default(), columns);
+
+    Ok(0)
+}
+
+fn fee_schedule(args: &InputArgs) -> FeeSchedule {
+    FeeSchedule {
+        house_client: args.fee_account,
+        withdrawal_flat: args.withdrawal_fee,
+        withdrawal_rate: args.withdrawal_fee_rate,
+        dispute: args.dispute_fee,
+        chargeback: args.chargeback_fee,
+    }
+}
+
+fn check_strict(args: &InputArgs) -> Result<(), Failure> {
+    if args.workers > 1 && args.strict {
+        return Err(Failure::Usage(
+            "--strict is not supported with --workers".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// input path, `stdin` for the standard input, with its records
+type Input<R> = (String, RecordReader<R>);
+
+// every file is opened before processing, so that no partial output is written
+fn open_inputs(args: &InputArgs) -> Result<Vec<Input<Box<dyn Read>>>, Failure> {
+    let csv_options = CsvOptions {
+        has_headers: !args.no_headers,
+        columns: args.columns.as_ref().map(|columns| {
+            columns
+                .iter()
+                .map(|column| column.trim().to_string())
+                .collect()
+        }),
+        delimiter: args.delimiter,
+    };
+    csv_options
+        .validate()
+        .map_err(|e| Failure::Usage(format!("invalid --columns: {}", e)))?;
+
+    let scale = args.input_scale.map(|places| InputScale {
+        places,
+        rounding: args.input_rounding,
+    });
+
+    // the input files can not contain admin records when there is an admin input
+    let open = |reader: Box<dyn Read>| {
+        let records = RecordReader::new(reader, args.input_format, &csv_options, scale);
+        match args.admin_input {
+            Some(_) => records.without_admin_records(),
+            None => records,
+        }
+    };
+
+    let mut inputs = match args.files.is_empty() {
+        true => vec![("stdin".to_string(), open(Box::new(io::stdin())))],
+        false => args
+            .files
+            .iter()
+            .map(|path| {
+                let reader: Box<dyn Read> = match path.as_str() {
+                    "-" => Box::new(io::stdin()),
+                    _ => Box::new(
+                        File::open(path)
+                            .map_err(io_failure(format!("failed to open file {}", path)))?,
                     ),
-                    "dispute" => {
-                        TxRecord::DISPUTE(TxRecordMetadata::new(csv_record.client, csv_record.tx))
-                    }
-                    "resolve" => {
-                        TxRecord::RESOLVE(TxRecordMetadata::new(csv_record.client, csv_record.tx))
-                    }
-                    "chargeback" => TxRecord::CHARGEBACK(TxRecordMetadata::new(
-                        csv_record.client,
-                        csv_record.tx,
-                    )),
-                    _ => {
-                        // print error and skip record
-                        eprintln!("unknown type: {}", csv_record.r#type);
-                        continue;
-                    }
-                })
+                };
+                let source = match path.as_str() {
+                    "-" => "stdin".to_string(),
+                    _ => path.clone(),
+                };
+                Ok((source, open(reader)))
+            })
+            .collect::<Result<Vec<_>, Failure>>()?,
+    };
+
+    if let Some(path) = &args.admin_input {
+        let file =
+            File::open(path).map_err(io_failure(format!("failed to open admin input {}", path)))?;
+        // the admin records correct the state left by the input files, e.g. unlock an account
+        // locked by a chargeback of the same batch
+        inputs.push((
+            path.clone(),
+            RecordReader::new(Box::new(file), args.input_format, &csv_options, scale),
+        ));
+    }
+
+    Ok(inputs)
+}
+
+fn create_rejects(args: &InputArgs) -> Result<Option<RejectWriter<BufWriter<File>>>, Failure> {
+    match &args.rejects {
+        Some(path) => {
+            let file = File::create(path).map_err(io_failure(format!(
+                "failed to create rejects file {}",
+                path
+            )))?;
+            Ok(Some(RejectWriter::new(
+                BufWriter::new(file),
+                args.rejects_format,
+            )))
+        }
+        None => Ok(None),
+    }
+}
+
+fn read_credit_limits(args: &InputArgs) -> Result<Vec<CreditLimit>, Failure> {
+    match &args.credit_limits {
+        Some(path) => {
+            let file = File::open(path).map_err(io_failure(format!(
+                "failed to open credit limits file {}",
+                path
+            )))?;
+            limits::read_credit_limits(file)
+                .map_err(|e| Failure::Other(format!("failed to load {}: {}", path, e)))
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+// the credit limits are set after loading, since they are not part of the state
+fn load_state<S: TransactionStore>(
+    config: TxProcConfig,
+    store: S,
+    path: Option<&str>,
+    limits: &[CreditLimit],
+) -> Result<TxProc<S>, Failure> {
+    let mut tx_proc = match path {
+        Some(path) => {
+            let file = File::open(path)
+                .map_err(io_failure(format!("failed to open state file {}", path)))?;
+            TxProc::load_with_store(config, store, file)
+                .map_err(|e| Failure::Other(format!("failed to load state {}: {}", path, e)))?
+        }
+        None => TxProc::with_store(config, store),
+    };
+    tx_proc.set_credit_limits(limits.iter().copied());
+    Ok(tx_proc)
+}
+
+fn run<S: TransactionStore + Send + 'static>(
+    inputs: Vec<Input<impl Read>>,
+    tx_proc: TxProc<S>,
+    args: &ProcessArgs,
+    rejects: Option<&mut RejectWriter<impl Write>>,
+    journal: Option<&mut JournalWriter<impl Write>>,
+) -> Result<u64, Failure> {
+    let (tx_proc, skipped) = process_inputs(inputs, tx_proc, &args.input, rejects, journal)?;
+
+    if let Some(path) = &args.state_out {
+        let file = File::create(path)
+            .map_err(io_failure(format!("failed to create state file {}", path)))?;
+        tx_proc
+            .save(file)
+            .map_err(io_failure(format!("failed to save state to {}", path)))?;
+    }
+
+    write_summaries_to_stdout(&tx_proc, &args.output)?;
+
+    Ok(skipped)
+}
+
+// returns the processed state with the number of skipped records
+fn process_inputs<S: TransactionStore + Send + 'static>(
+    inputs: Vec<Input<impl Read>>,
+    mut tx_proc: TxProc<S>,
+    args: &InputArgs,
+    mut rejects: Option<&mut RejectWriter<impl Write>>,
+    mut journal: Option<&mut JournalWriter<impl Write>>,
+) -> Result<(TxProc<S>, u64), Failure> {
+    if args.workers > 1 {
+        return process_records_sharded(inputs, tx_proc, args.workers, rejects);
+    }
+
+    let mut skipped = 0;
+    for (source, records) in inputs {
+        skipped += process_records(
+            &source,
+            records,
+            &mut tx_proc,
+            rejects.as_deref_mut(),
+            journal.as_deref_mut(),
+            args.strict,
+        )?;
+    }
+
+    Ok((tx_proc, skipped))
+}
+
+// records decoded from an input file, in the format given by --input-format
+enum RecordReader<R: Read> {
+    Csv(TxRecordReader<R>),
+    Jsonl(TxRecordJsonReader<R>),
+}
+
+impl<R: Read> RecordReader<R> {
+    // the CSV options are ignored by the other formats
+    fn new(
+        reader: R,
+        format: InputFormat,
+        csv_options: &CsvOptions,
+        scale: Option<InputScale>,
+    ) -> RecordReader<R> {
+        match format {
+            InputFormat::Csv => {
+                let mut records = TxRecordReader::with_options(reader, csv_options);
+                if let Some(scale) = scale {
+                    records = records.with_scale(scale);
+                }
+                RecordReader::Csv(records)
             }
-            Err(e) => {
-                // print error and skip record
-                eprintln!("csv error: {}", e);
+            InputFormat::Jsonl => {
+                let mut records = TxRecordJsonReader::new(reader);
+                if let Some(scale) = scale {
+                    records = records.with_scale(scale);
+                }
+                RecordReader::Jsonl(records)
             }
         }
     }
 
-    tx_proc
+    fn without_admin_records(self) -> RecordReader<R> {
+        match self {
+            RecordReader::Csv(records) => RecordReader::Csv(records.without_admin_records()),
+            RecordReader::Jsonl(records) => RecordReader::Jsonl(records.without_admin_records()),
+        }
+    }
+
+    fn line(&self) -> Option<u64> {
+        match self {
+            RecordReader::Csv(records) => records.line(),
+            RecordReader::Jsonl(records) => records.line(),
+        }
+    }
+
+    fn raw_record(&self) -> String {
+        match self {
+            RecordReader::Csv(records) => records.raw_record(),
+            RecordReader::Jsonl(records) => records.raw_record(),
+        }
+    }
 }
 
-fn write_account_csv_to_stdout(proc: &TxProc) {
-    let mut wtr = csv::Writer::from_writer(io::stdout());
+impl<R: Read> Iterator for RecordReader<R> {
+    // records that can not be decoded are given as (reason, message)
+    type Item = Result<TxRecord, (&'static str, String)>;
 
-    #[derive(Serialize)]
-    struct CsvLineOutput {
-        client: u16,
-        available: Decimal,
-        held: Decimal,
-        total: Decimal,
-        locked: bool,
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            RecordReader::Csv(records) => records
+                .next()
+                .map(|record| record.map_err(|e| (e.code(), e.to_string()))),
+            RecordReader::Jsonl(records) => records
+                .next()
+                .map(|record| record.map_err(|e| (e.code(), e.to_string()))),
+        }
     }
+}
 
-    for (client_id, account_summary) in proc.summary_iterator() {
-        match wtr.serialize(CsvLineOutput {
-            client: *client_id,
-            available: account_summary.available_amount(),
-            held: account_summary.held_amount(),
-            total: account_summary.total_amount(),
-            locked: account_summary.is_locked(),
-        }) {
-            Ok(_) => {}
-            // todo this case is not documented nor tested
-            // todo is skipping the record ok or should the process completely fail ?
-            Err(e) => {
-                eprintln!("error while serializing record {}", e)
+// returns the number of skipped records
+//
+// with `strict`, stops at the first skipped record with a `Failure::Halted`
+fn process_records<S: TransactionStore>(
+    source: &str,
+    mut records: RecordReader<impl Read>,
+    tx_proc: &mut TxProc<S>,
+    mut rejects: Option<&mut RejectWriter<impl Write>>,
+    mut journal: Option<&mut JournalWriter<impl Write>>,
+    strict: bool,
+) -> Result<u64, Failure> {
+    let mut skipped = 0;
+
+    while let Some(record) = records.next() {
+        let (reason, message) = match record {
+            Ok(tx_record) => match tx_proc.submit_tx_record(tx_record) {
+                Ok(_) => {
+                    write_journal(tx_proc, journal.as_deref_mut())?;
+                    continue;
+                }
+                Err(rejection) => {
+                    // a rejected record may still open an account
+                    write_journal(tx_proc, journal.as_deref_mut())?;
+                    // print rejection reason, the record has been skipped
+                    eprintln!("rejected record: {}", rejection);
+                    (rejection.code(), rejection.to_string())
+                }
+            },
+            Err((reason, message)) => {
+                // print error and skip record
+                eprintln!("{}", message);
+                (reason, message)
             }
+        };
+
+        skipped += 1;
+
+        if let Some(rejects) = rejects.as_mut() {
+            rejects
+                .write(
+                    source,
+                    records.line(),
+                    &records.raw_record(),
+                    reason,
+                    &message,
+                )
+                .map_err(io_failure("failed to write to the rejects file"))?;
+        }
+
+        if strict {
+            let line = records.line().map_or_else(
+                || "unknown line".to_string(),
+                |line| format!("line {}", line),
+            );
+            return Err(Failure::Halted(format!(
+                "stopped at {} {}, {}: {}",
+                source, line, reason, message
+            )));
         }
     }
 
+    Ok(skipped)
+}
+
+// returns the processed state with the number of skipped records
+fn process_records_sharded<S: TransactionStore + Send + 'static>(
+    inputs: Vec<Input<impl Read>>,
+    tx_proc: TxProc<S>,
+    workers: usize,
+    rejects: Option<&mut RejectWriter<impl Write>>,
+) -> Result<(TxProc<S>, u64), Failure> {
+    let mut sharded = ShardedTxProc::new(tx_proc, workers);
+
+    // (input sequence, input index, line, raw record, reason, message)
+    let mut skipped = Vec::new();
+    let mut sequence: u64 = 0;
+
+    let mut sources = Vec::new();
+
+    for (input, (source, mut records)) in inputs.into_iter().enumerate() {
+        sources.push(source);
+        while let Some(record) = records.next() {
+            sequence += 1;
+            match record {
+                Ok(tx_record) => sharded.submit_tx_record(
+                    tx_record,
+                    (sequence, input, records.line(), records.raw_record()),
+                ),
+                Err((reason, message)) => {
+                    // print error and skip record
+                    eprintln!("{}", message);
+                    skipped.push((
+                        sequence,
+                        input,
+                        records.line(),
+                        records.raw_record(),
+                        reason,
+                        message,
+                    ));
+                }
+            }
+        }
+    }
+
+    let (tx_proc, rejected) = sharded.finish();
+
+    for ((sequence, input, line, raw_record), rejection) in rejected {
+        // print rejection reason, the record has been skipped
+        eprintln!("rejected record: {}", rejection);
+        skipped.push((
+            sequence,
+            input,
+            line,
+            raw_record,
+            rejection.code(),
+            rejection.to_string(),
+        ));
+    }
+
+    let skipped_count = skipped.len() as u64;
+
+    if let Some(rejects) = rejects {
+        // parse errors and rejections are reported in input order
+        skipped.sort_by_key(|(sequence, ..)| *sequence);
+        for (_, input, line, raw_record, reason, message) in skipped {
+            rejects
+                .write(&sources[input], line, &raw_record, reason, &message)
+                .map_err(io_failure("failed to write to the rejects file"))?;
+        }
+    }
+
+    Ok((tx_proc, skipped_count))
+}
+
+fn write_journal<S: TransactionStore>(
+    tx_proc: &mut TxProc<S>,
+    journal: Option<&mut JournalWriter<impl Write>>,
+) -> Result<(), Failure> {
+    if let Some(journal) = journal {
+        for event in tx_proc.drain_journal() {
+            journal
+                .write(&event)
+                .map_err(io_failure("failed to write to the journal file"))?;
+        }
+    }
+    Ok(())
+}
+
+// optional columns of the account summaries
+#[derive(Clone, Copy)]
+struct Columns {
+    currency: bool,
+    credit_limit: bool,
+    negative: bool,
+    adjustments: bool,
+    fees: bool,
+    authorizations: bool,
+}
+
+fn write_summaries_to_stdout<S: TransactionStore>(
+    tx_proc: &TxProc<S>,
+    args: &OutputArgs,
+) -> Result<(), Failure> {
+    let summaries: Box<dyn Iterator<Item = (&u16, &AccountSummary)>> = match args.order {
+        SummaryOrder::Client => Box::new(tx_proc.summary_iterator()),
+        SummaryOrder::ClientDesc => Box::new(tx_proc.summary_iterator().rev()),
+    };
+
+    let scale = args.output_scale.map(|places| OutputScale {
+        places,
+        rounding: args.output_rounding,
+    });
+
+    // optional columns follow the arguments, not the data, so that the header is the same
+    // from one batch to the next
+    let columns = Columns {
+        currency: args.currency_column,
+        credit_limit: tx_proc.has_credit_limits(),
+        negative: args.negative_flag,
+        adjustments: args.adjustment_columns,
+        fees: tx_proc.has_fees(),
+        authorizations: args.authorized_column,
+    };
+
+    if !args.currency_column && tx_proc.has_currencies() {
+        eprintln!("warning: accounts in several currencies written without --currency-column");
+    }
+
+    match args.output_format {
+        OutputFormat::Csv => write_account_csv_to_stdout(summaries, scale, columns),
+        OutputFormat::Json => write_account_json_to_stdout(
+            summaries,
+            AccountSummaryJsonWriter::array(io::stdout()),
+            scale,
+            columns,
+        ),
+        OutputFormat::Jsonl => write_account_json_to_stdout(
+            summaries,
+            AccountSummaryJsonWriter::lines(io::stdout()),
+            scale,
+            columns,
+        ),
+    }
+    .map_err(io_failure(
+        "failed to write the account summaries to stdout",
+    ))
+}
+
+fn write_account_csv_to_stdout<'a>(
+    summaries: impl Iterator<Item = (&'a u16, &'a AccountSummary)>,
+    scale: Option<OutputScale>,
+    columns: Columns,
+) -> io::Result<()> {
+    let mut wtr = AccountSummaryWriter::new(io::stdout());
+    if let Some(scale) = scale {
+        wtr = wtr.with_scale(scale);
+    }
+    if columns.currency {
+        wtr = wtr.with_currency();
+    }
+    if columns.credit_limit {
+        wtr = wtr.with_credit_limit();
+    }
+    if columns.negative {
+        wtr = wtr.with_negative_flag();
+    }
+    if columns.adjustments {
+        wtr = wtr.with_adjustments();
+    }
+    if columns.fees {
+        wtr = wtr.with_fees();
+    }
+    if columns.authorizations {
+        wtr = wtr.with_authorizations();
+    }
+
+    for (client_id, account_summary) in summaries {
+        wtr.write(*client_id, account_summary)?;
+    }
+
     wtr.flush()
-        .expect("error while trying to flush csv to stdout");
+}
+
+fn write_account_json_to_stdout<'a>(
+    summaries: impl Iterator<Item = (&'a u16, &'a AccountSummary)>,
+    mut writer: AccountSummaryJsonWriter<impl Write>,
+    scale: Option<OutputScale>,
+    columns: Columns,
+) -> io::Result<()> {
+    if let Some(scale) = scale {
+        writer = writer.with_scale(scale);
+    }
+    if columns.currency {
+        writer = writer.with_currency();
+    }
+    if columns.credit_limit {
+        writer = writer.with_credit_limit();
+    }
+    if columns.negative {
+        writer = writer.with_negative_flag();
+    }
+    if columns.adjustments {
+        writer = writer.with_adjustments();
+    }
+    if columns.fees {
+        writer = writer.with_fees();
+    }
+    if columns.authorizations {
+        writer = writer.with_authorizations();
+    }
+
+    for (client_id, account_summary) in summaries {
+        writer.write(*client_id, account_summary)?;
+    }
+
+    writer.finish()
 }
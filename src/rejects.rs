@@ -0,0 +1,84 @@
+use serde::Serialize;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// A record skipped either by the parser or by [`crate::TxProc`]
+#[derive(Serialize)]
+struct RejectLine<'a> {
+    input: &'a str,
+    line: Option<u64>,
+    record: &'a str,
+    reason: &'a str,
+    message: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectFormat {
+    Csv,
+    Jsonl,
+}
+
+impl FromStr for RejectFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RejectFormat, String> {
+        match s {
+            "csv" => Ok(RejectFormat::Csv),
+            "jsonl" => Ok(RejectFormat::Jsonl),
+            _ => Err(format!("unknown rejects format: {}", s)),
+        }
+    }
+}
+
+/// Writer encoding skipped records as `input, line, record, reason, message` CSV lines or JSON lines
+///
+/// `input` is the file the record was read from, or `stdin`,
+/// `reason` is a stable code such as `negative_amount` or `malformed_line`,
+/// `message` is the human-readable explanation
+pub enum RejectWriter<W: Write> {
+    Csv(Box<::csv::Writer<W>>),
+    Jsonl(W),
+}
+
+impl<W: Write> RejectWriter<W> {
+    pub fn new(writer: W, format: RejectFormat) -> RejectWriter<W> {
+        match format {
+            RejectFormat::Csv => RejectWriter::Csv(Box::new(::csv::Writer::from_writer(writer))),
+            RejectFormat::Jsonl => RejectWriter::Jsonl(writer),
+        }
+    }
+
+    pub fn write(
+        &mut self,
+        input: &str,
+        line: Option<u64>,
+        record: &str,
+        reason: &str,
+        message: &str,
+    ) -> io::Result<()> {
+        let reject = RejectLine {
+            input,
+            line,
+            record,
+            reason,
+            message,
+        };
+
+        match self {
+            RejectWriter::Csv(writer) => writer.serialize(reject)?,
+            RejectWriter::Jsonl(writer) => {
+                serde_json::to_writer(&mut *writer, &reject)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self {
+            RejectWriter::Csv(writer) => writer.flush(),
+            RejectWriter::Jsonl(writer) => writer.flush(),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::str::FromStr;
+
+/// largest number of decimal places of a [`Decimal`]
+pub const MAX_PLACES: u32 = 28;
+
+/// Rounding applied to the amounts with more decimal places than the scale
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// to the nearest, ties to the even neighbour (banker's rounding)
+    #[default]
+    HalfEven,
+    /// to the nearest, ties away from zero
+    HalfUp,
+    /// toward zero, i.e. truncation
+    Down,
+    /// away from zero
+    Up,
+}
+
+impl Rounding {
+    fn strategy(self) -> RoundingStrategy {
+        match self {
+            Rounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::Down => RoundingStrategy::ToZero,
+            Rounding::Up => RoundingStrategy::AwayFromZero,
+        }
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Rounding, String> {
+        match s {
+            "half-even" => Ok(Rounding::HalfEven),
+            "half-up" => Ok(Rounding::HalfUp),
+            "down" => Ok(Rounding::Down),
+            "up" => Ok(Rounding::Up),
+            _ => Err(format!("unknown rounding: {}", s)),
+        }
+    }
+}
+
+/// Decimal places accepted on input amounts
+///
+/// amounts with fewer places are kept as is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputScale {
+    pub places: u32,
+    /// amounts with more places are rounded, or rejected when `None`
+    pub rounding: Option<Rounding>,
+}
+
+impl InputScale {
+    /// the amount within the scale, `None` if it has too many places and rounding is disabled
+    pub fn apply(&self, amount: Decimal) -> Option<Decimal> {
+        if amount.scale() <= self.places {
+            return Some(amount);
+        }
+        self.rounding
+            .map(|rounding| amount.round_dp_with_strategy(self.places, rounding.strategy()))
+    }
+}
+
+/// Decimal places of output amounts, e.g. `1.5` is written `1.5000` with 4 places
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputScale {
+    pub places: u32,
+    pub rounding: Rounding,
+}
+
+impl OutputScale {
+    /// the amount rounded, then padded with zeros, to exactly `places` decimal places
+    pub fn apply(&self, amount: Decimal) -> Decimal {
+        let mut amount = amount.round_dp_with_strategy(self.places, self.rounding.strategy());
+        amount.rescale(self.places);
+        amount
+    }
+}
@@ -0,0 +1,189 @@
+use crate::csv::{AccountSummaryWriter, TxRecordReader};
+use crate::store::TransactionStore;
+use crate::{Applied, TxProc, TxRecord, TxRejection};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+/// Connections handled at the same time, the next ones wait in the listener backlog
+pub const MAX_CONNECTIONS: usize = 64;
+
+// messages sent by the connection threads to the thread owning the TxProc
+enum Request {
+    Record(TxRecord, Sender<Result<Applied, TxRejection>>),
+    Summary(Sender<Vec<u8>>),
+}
+
+/// Optional columns of the summaries answered by the server
+///
+/// they are fixed for the lifetime of the server, so that every answer has the same header
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SummaryColumns {
+    /// `currency` column, after `client`
+    pub currency: bool,
+    /// `adjustments` and `adjusted` columns, see [`crate::AccountSummary::adjustments`],
+    /// end users can not submit adjustments but `tx_proc` may have been loaded with some
+    pub adjustments: bool,
+    /// `authorized` column, see [`crate::AccountSummary::authorized_amount`]
+    pub authorizations: bool,
+}
+
+/// Accepts connections on `listener`, each connection running on its own thread,
+/// up to [`MAX_CONNECTIONS`] at the same time
+///
+/// a failed connection is logged and does not stop the server
+///
+/// a connection starts with a command line:
+///
+/// `SUBMIT` is followed by CSV in the `type, client, tx, amount` format, header included,
+/// until the client shuts down its write half, the server then answers with
+/// `accepted,rejected,malformed` CSV counts and closes the connection
+///
+/// `SUMMARY` is answered with the account summaries CSV, with the given optional `columns`,
+/// then the connection is closed
+///
+/// records of all the connections are funneled to a single thread owning `tx_proc`,
+/// records of a connection are processed in order
+pub fn serve<S: TransactionStore + Send + 'static>(
+    listener: TcpListener,
+    tx_proc: TxProc<S>,
+    columns: SummaryColumns,
+) {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || own_tx_proc(tx_proc, columns, receiver));
+
+    // every connection thread tells when it ends, so that the running ones can be counted
+    let (ended_sender, ended_receiver) = mpsc::channel();
+    let mut running = 0;
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+
+        running -= ended_receiver.try_iter().count();
+        // waits for a connection to end
+        if running == MAX_CONNECTIONS && ended_receiver.recv().is_ok() {
+            running -= 1;
+        }
+
+        let sender = sender.clone();
+        let ended_sender = ended_sender.clone();
+        running += 1;
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, sender) {
+                eprintln!("connection error: {}", e);
+            }
+            let _ = ended_sender.send(());
+        });
+    }
+}
+
+fn own_tx_proc<S: TransactionStore>(
+    mut tx_proc: TxProc<S>,
+    columns: SummaryColumns,
+    receiver: Receiver<Request>,
+) {
+    for request in receiver {
+        match request {
+            Request::Record(record, result_sender) => {
+                // the connection may have been closed in the meantime
+                let _ = result_sender.send(tx_proc.submit_tx_record(record));
+            }
+            Request::Summary(summary_sender) => {
+                let mut summary = Vec::new();
+                let mut writer = AccountSummaryWriter::new(&mut summary);
+                if columns.currency {
+                    writer = writer.with_currency();
+                }
+                if columns.adjustments {
+                    writer = writer.with_adjustments();
+                }
+                if tx_proc.has_fees() {
+                    writer = writer.with_fees();
+                }
+                if columns.authorizations {
+                    writer = writer.with_authorizations();
+                }
+                for (client_id, account_summary) in tx_proc.summary_iterator() {
+                    if let Err(e) = writer.write(*client_id, account_summary) {
+                        eprintln!("error while serializing record {}", e);
+                    }
+                }
+                if let Err(e) = writer.flush() {
+                    eprintln!("error while serializing summaries {}", e);
+                }
+                drop(writer);
+                let _ = summary_sender.send(summary);
+            }
+        }
+    }
+}
+
+fn handle_connection(stream: TcpStream, sender: Sender<Request>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    let mut command = String::new();
+    reader.read_line(&mut command)?;
+
+    match command.trim() {
+        "SUBMIT" => {
+            let (result_sender, result_receiver) = mpsc::channel();
+
+            let mut submitted = 0;
+            let mut malformed = 0;
+            // streams come from end users, who can not unlock accounts
+            for record in TxRecordReader::new(reader).without_admin_records() {
+                match record {
+                    Ok(record) => {
+                        sender
+                            .send(Request::Record(record, result_sender.clone()))
+                            .map_err(|_| owner_stopped())?;
+                        submitted += 1;
+                    }
+                    Err(_) => malformed += 1,
+                }
+            }
+
+            let mut accepted = 0;
+            let mut rejected = 0;
+            for result in result_receiver.iter().take(submitted) {
+                match result {
+                    Ok(_) => accepted += 1,
+                    Err(_) => rejected += 1,
+                }
+            }
+
+            write!(
+                writer,
+                "accepted,rejected,malformed\n{},{},{}\n",
+                accepted, rejected, malformed
+            )?;
+        }
+        "SUMMARY" => {
+            let (summary_sender, summary_receiver) = mpsc::channel();
+            sender
+                .send(Request::Summary(summary_sender))
+                .map_err(|_| owner_stopped())?;
+            let summary = summary_receiver.recv().map_err(|_| owner_stopped())?;
+            writer.write_all(&summary)?;
+        }
+        command => {
+            writeln!(writer, "unknown command: {}", command)?;
+        }
+    }
+
+    writer.flush()?;
+    writer.shutdown(Shutdown::Both)
+}
+
+fn owner_stopped() -> io::Error {
+    io::Error::other("the thread owning the account summaries stopped")
+}
@@ -0,0 +1,343 @@
+use crate::store::TransactionStore;
+use crate::{
+    AccountKey, AccountSummary, TxProc, TxProcConfig, TxRecord, TxRecordFlags, TxRejection,
+};
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+/// Processes records on several threads, each thread owning the accounts of a subset of clients
+///
+/// records are routed by client id so that the records of a client are processed in order,
+/// the transaction store is shared by all the shards so that transaction ids are unique globally
+///
+/// when a transaction id is referenced by a client of another shard than the previous reference,
+/// the record waits for the previous shard to catch up, so the result is identical to a sequential run
+///
+/// a transfer between clients of different shards, the chargeback of such a transfer,
+/// or a record charged a fee for the house account of another shard,
+/// is applied by the router itself, on the accounts of every client taken from their shards
+pub struct ShardedTxProc<S: TransactionStore + Send + 'static, T: Send + 'static = ()> {
+    shards: Vec<Shard<S, T>>,
+    store: Arc<Mutex<S>>,
+    config: TxProcConfig,
+    credit_limits: HashMap<AccountKey, Decimal>,
+    // applies the records moving funds between two shards, holds no account in between
+    router: TxProc<SharedStore<S>>,
+    // records rejected by the router
+    rejected: Vec<Rejected<T>>,
+    // shard of the last record referencing each transaction id
+    last_shards: HashMap<u32, usize>,
+    sequence: u64,
+}
+
+// accounts of the shard and the records it rejected
+type ShardOutcome<S, T> = (TxProc<SharedStore<S>>, Vec<Rejected<T>>);
+
+struct Shard<S: TransactionStore, T> {
+    sender: Sender<ShardMessage<T>>,
+    worker: JoinHandle<ShardOutcome<S, T>>,
+}
+
+enum ShardMessage<T> {
+    Record(u64, TxRecord, T),
+    // answered once every previous record of the shard has been processed
+    Sync(Sender<()>),
+    // removes the accounts of the client, answered like a sync
+    Take(u16, Sender<Vec<(AccountKey, AccountSummary)>>),
+    // gives an account back, after a record applied by the router
+    Put(AccountKey, AccountSummary),
+}
+
+struct Rejected<T> {
+    sequence: u64,
+    tag: T,
+    rejection: TxRejection,
+}
+
+impl<S: TransactionStore + Send + 'static, T: Send + 'static> ShardedTxProc<S, T> {
+    /// distributes the accounts of `tx_proc` between `shard_count` threads
+    ///
+    /// the journal and the strict mode are not supported,
+    /// [`crate::TxProcConfig::journal`] and [`crate::TxProcConfig::strict`] are ignored
+    pub fn new(tx_proc: TxProc<S>, shard_count: usize) -> ShardedTxProc<S, T> {
+        let shard_count = shard_count.max(1);
+
+        let mut config = tx_proc.config;
+        config.journal = false;
+        // a rejection would only halt the shard of the client
+        config.strict = false;
+
+        let store = Arc::new(Mutex::new(tx_proc.transactions));
+
+        let mut shard_procs: Vec<TxProc<SharedStore<S>>> = (0..shard_count)
+            .map(|_| TxProc::with_store(config, SharedStore(store.clone())))
+            .collect();
+        let mut router = TxProc::with_store(config, SharedStore(store.clone()));
+        router.credit_limits = tx_proc.credit_limits.clone();
+        for shard_proc in &mut shard_procs {
+            shard_proc.credit_limits = tx_proc.credit_limits.clone();
+        }
+        for ((client_id, currency), summary) in tx_proc.account_summaries {
+            shard_procs[shard_index(client_id, shard_count)]
+                .account_summaries
+                .insert((client_id, currency), summary);
+        }
+
+        let shards = shard_procs
+            .into_iter()
+            .map(|mut shard_proc| {
+                let (sender, receiver) = mpsc::channel();
+                let worker = thread::spawn(move || {
+                    let rejected = run_shard(&mut shard_proc, receiver);
+                    (shard_proc, rejected)
+                });
+                Shard { sender, worker }
+            })
+            .collect();
+
+        ShardedTxProc {
+            shards,
+            store,
+            config: tx_proc.config,
+            credit_limits: tx_proc.credit_limits,
+            router,
+            rejected: Vec::new(),
+            last_shards: HashMap::new(),
+            sequence: 0,
+        }
+    }
+
+    /// queues the record on the shard of its client
+    ///
+    /// `tag` is given back with the rejection reason by [`ShardedTxProc::finish`] if the record is rejected
+    pub fn submit_tx_record(&mut self, record: TxRecord, tag: T) {
+        let metadata = record.record_metadata();
+        let shard = shard_index(metadata.client_id, self.shards.len());
+
+        match self.last_shards.insert(metadata.tx_id, shard) {
+            Some(last_shard) if last_shard != shard => self.sync(last_shard),
+            _ => {}
+        }
+
+        self.sequence += 1;
+
+        let other_shard = self
+            .counterpart_clients(&record)
+            .iter()
+            .any(|client| shard_index(*client, self.shards.len()) != shard);
+        if other_shard {
+            self.apply_across_shards(record, tag);
+            return;
+        }
+
+        self.shards[shard]
+            .sender
+            .send(ShardMessage::Record(self.sequence, record, tag))
+            .expect("shard worker stopped");
+    }
+
+    // clients whose accounts may be moved by the record besides its own client: the destination
+    // of a transfer, the source of a charged back transfer, the house account of a fee
+    fn counterpart_clients(&self, record: &TxRecord) -> Vec<u16> {
+        let mut clients = Vec::new();
+        match *record {
+            TxRecord::TRANSFER(_, destination, _) => clients.push(destination),
+            // a transfer within a shard may still be queued, it is then not found
+            TxRecord::CHARGEBACK(ref metadata) => {
+                if let Some(Ok(Some((TxRecord::TRANSFER(source, _, _), _)))) = self
+                    .store
+                    .lock()
+                    .ok()
+                    .map(|store| store.get(metadata.tx_id))
+                {
+                    clients.push(source.client_id);
+                }
+            }
+            _ => {}
+        }
+        if self.config.fees.may_charge(record) {
+            clients.push(self.config.fees.house_client);
+        }
+        clients
+    }
+
+    // every account the record may move is taken from its shard, whatever the shard,
+    // so that the accounts given back never replace the ones left in a shard
+    fn apply_across_shards(&mut self, record: TxRecord, tag: T) {
+        let client_id = record.record_metadata().client_id;
+        self.take(client_id);
+
+        // the shard of the client has caught up, the transfer of a chargeback is then stored
+        let mut taken = vec![client_id];
+        for client in self.counterpart_clients(&record) {
+            if !taken.contains(&client) {
+                self.take(client);
+                taken.push(client);
+            }
+        }
+
+        if let Err(rejection) = self.router.submit_tx_record(record) {
+            self.rejected.push(Rejected {
+                sequence: self.sequence,
+                tag,
+                rejection,
+            });
+        }
+
+        // including the accounts opened by the record
+        for (key, summary) in std::mem::take(&mut self.router.account_summaries) {
+            self.shards[shard_index(key.0, self.shards.len())]
+                .sender
+                .send(ShardMessage::Put(key, summary))
+                .expect("shard worker stopped");
+        }
+    }
+
+    // moves the accounts of the client from its shard to the router
+    fn take(&mut self, client: u16) {
+        let (sender, receiver) = mpsc::channel();
+        self.shards[shard_index(client, self.shards.len())]
+            .sender
+            .send(ShardMessage::Take(client, sender))
+            .expect("shard worker stopped");
+        self.router
+            .account_summaries
+            .extend(receiver.recv().expect("shard worker stopped"));
+    }
+
+    fn sync(&self, shard: usize) {
+        let (sender, receiver) = mpsc::channel();
+        self.shards[shard]
+            .sender
+            .send(ShardMessage::Sync(sender))
+            .expect("shard worker stopped");
+        receiver.recv().expect("shard worker stopped");
+    }
+
+    /// waits for every queued record to be processed and merges the shards back
+    ///
+    /// rejected records are returned in submission order, with their tag
+    pub fn finish(self) -> (TxProc<S>, Vec<(T, TxRejection)>) {
+        let mut account_summaries = BTreeMap::new();
+        let mut rejected = self.rejected;
+
+        for shard in self.shards {
+            drop(shard.sender);
+            let (shard_proc, shard_rejected) = shard.worker.join().expect("shard worker panicked");
+            account_summaries.extend(shard_proc.account_summaries);
+            rejected.extend(shard_rejected);
+        }
+
+        rejected.sort_by_key(|rejected| rejected.sequence);
+
+        // releases its reference to the store
+        drop(self.router);
+
+        let store = match Arc::try_unwrap(self.store) {
+            Ok(store) => store.into_inner().unwrap_or_else(|e| e.into_inner()),
+            Err(_) => unreachable!("shard workers have been joined"),
+        };
+
+        let mut tx_proc = TxProc::with_store(self.config, store);
+        tx_proc.account_summaries = account_summaries;
+        tx_proc.credit_limits = self.credit_limits;
+
+        (
+            tx_proc,
+            rejected
+                .into_iter()
+                .map(|rejected| (rejected.tag, rejected.rejection))
+                .collect(),
+        )
+    }
+}
+
+fn shard_index(client_id: u16, shard_count: usize) -> usize {
+    client_id as usize % shard_count
+}
+
+fn run_shard<S: TransactionStore, T>(
+    shard_proc: &mut TxProc<SharedStore<S>>,
+    receiver: Receiver<ShardMessage<T>>,
+) -> Vec<Rejected<T>> {
+    let mut rejected = Vec::new();
+
+    for message in receiver {
+        match message {
+            ShardMessage::Record(sequence, record, tag) => {
+                if let Err(rejection) = shard_proc.submit_tx_record(record) {
+                    rejected.push(Rejected {
+                        sequence,
+                        tag,
+                        rejection,
+                    });
+                }
+            }
+            ShardMessage::Sync(sender) => {
+                // the router may have given up waiting
+                let _ = sender.send(());
+            }
+            ShardMessage::Take(client, sender) => {
+                let keys: Vec<AccountKey> = shard_proc
+                    .account_summaries
+                    .keys()
+                    .filter(|(client_id, _)| *client_id == client)
+                    .copied()
+                    .collect();
+                let taken = keys
+                    .into_iter()
+                    .filter_map(|key| {
+                        let summary = shard_proc.account_summaries.remove(&key)?;
+                        Some((key, summary))
+                    })
+                    .collect();
+                let _ = sender.send(taken);
+            }
+            ShardMessage::Put(key, summary) => {
+                shard_proc.account_summaries.insert(key, summary);
+            }
+        }
+    }
+
+    rejected
+}
+
+// transaction store shared by the shards
+struct SharedStore<S>(Arc<Mutex<S>>);
+
+impl<S: TransactionStore> SharedStore<S> {
+    fn lock(&self) -> io::Result<MutexGuard<'_, S>> {
+        self.0
+            .lock()
+            .map_err(|_| io::Error::other("transaction store poisoned by a panicked shard"))
+    }
+}
+
+impl<S: TransactionStore> TransactionStore for SharedStore<S> {
+    fn get(&self, tx_id: u32) -> io::Result<Option<(TxRecord, TxRecordFlags)>> {
+        self.lock()?.get(tx_id)
+    }
+
+    fn contains(&self, tx_id: u32) -> io::Result<bool> {
+        self.lock()?.contains(tx_id)
+    }
+
+    fn insert_if_absent(&mut self, record: TxRecord) -> io::Result<bool> {
+        self.lock()?.insert_if_absent(record)
+    }
+
+    fn update_flags(&mut self, tx_id: u32, flags: TxRecordFlags) -> io::Result<()> {
+        self.lock()?.update_flags(tx_id, flags)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = io::Result<(TxRecord, TxRecordFlags)>> + '_> {
+        match self.lock() {
+            Ok(store) => Box::new(store.iter().collect::<Vec<_>>().into_iter()),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        }
+    }
+}
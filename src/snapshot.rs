@@ -0,0 +1,375 @@
+use crate::store::{InMemoryStore, TransactionStore};
+use crate::{
+    AccountSummary, Bucket, Currency, TxProc, TxProcConfig, TxRecord, TxRecordFlags,
+    TxRecordMetadata, WithdrawalDisputePolicy,
+};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// version written in the header of the snapshots, bumped on incompatible format changes
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// a snapshot is a JSON lines file: a header line,
+// then one line per account summary and one line per recorded transaction,
+// the currency is omitted for the default currency
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SnapshotLine {
+    Header {
+        version: u32,
+    },
+    Account {
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        available: Decimal,
+        held: Decimal,
+        locked: bool,
+        #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+        shortfall: Decimal,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        adjustments: u32,
+        #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+        adjusted_amount: Decimal,
+        #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+        fees: Decimal,
+        #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+        authorized: Decimal,
+    },
+    Transaction {
+        r#type: SnapshotTxType,
+        client: u16,
+        #[serde(default, skip_serializing_if = "Currency::is_default")]
+        currency: Currency,
+        tx: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        destination: Option<u16>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bucket: Option<Bucket>,
+        amount: Decimal,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+        disputed: bool,
+        chargedback: bool,
+        #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+        shortfall: Decimal,
+        #[serde(default, skip_serializing_if = "is_false")]
+        captured: bool,
+        #[serde(default, skip_serializing_if = "is_false")]
+        voided: bool,
+        #[serde(default, skip_serializing_if = "is_false")]
+        declined: bool,
+        // withdrawal dispute policy of a disputed withdrawal
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        policy: Option<WithdrawalDisputePolicy>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SnapshotTxType {
+    Deposit,
+    Withdrawal,
+    Transfer,
+    Adjustment,
+    Authorization,
+}
+
+fn is_zero(count: &u32) -> bool {
+    *count == 0
+}
+
+fn is_false(flag: &bool) -> bool {
+    !*flag
+}
+
+impl TxProc {
+    /// restores a snapshot written by [`TxProc::save`], keeping the transactions in memory
+    pub fn load(reader: impl Read) -> Result<TxProc, SnapshotError> {
+        TxProc::load_with_store(TxProcConfig::default(), InMemoryStore::default(), reader)
+    }
+}
+
+impl<S: TransactionStore> TxProc<S> {
+    /// writes the account summaries and the recorded transactions with their flags
+    ///
+    /// the configuration is not part of the snapshot
+    pub fn save(&self, writer: impl Write) -> io::Result<()> {
+        fn write_line(writer: &mut impl Write, line: &SnapshotLine) -> io::Result<()> {
+            serde_json::to_writer(&mut *writer, line)?;
+            writer.write_all(b"\n")
+        }
+
+        let mut writer = io::BufWriter::new(writer);
+
+        write_line(
+            &mut writer,
+            &SnapshotLine::Header {
+                version: SNAPSHOT_VERSION,
+            },
+        )?;
+
+        for (client_id, summary) in self.summary_iterator() {
+            write_line(
+                &mut writer,
+                &SnapshotLine::Account {
+                    client: *client_id,
+                    currency: summary.currency,
+                    available: summary.available_amount,
+                    held: summary.held_amount,
+                    locked: summary.locked,
+                    shortfall: summary.shortfall,
+                    adjustments: summary.adjustments,
+                    adjusted_amount: summary.adjusted_amount,
+                    fees: summary.fees,
+                    authorized: summary.authorized_amount,
+                },
+            )?;
+        }
+
+        for transaction in self.transactions.iter() {
+            let (record, flags) = transaction?;
+            // (type, metadata, destination, bucket, amount, reason)
+            let (r#type, metadata, destination, bucket, amount, reason) = match record {
+                TxRecord::DEPOSIT(metadata, amount) => {
+                    (SnapshotTxType::Deposit, metadata, None, None, amount, None)
+                }
+                TxRecord::WITHDRAWAL(metadata, amount) => (
+                    SnapshotTxType::Withdrawal,
+                    metadata,
+                    None,
+                    None,
+                    amount,
+                    None,
+                ),
+                TxRecord::TRANSFER(metadata, destination, amount) => (
+                    SnapshotTxType::Transfer,
+                    metadata,
+                    Some(destination),
+                    None,
+                    amount,
+                    None,
+                ),
+                TxRecord::ADJUSTMENT(metadata, bucket, amount, reason) => (
+                    SnapshotTxType::Adjustment,
+                    metadata,
+                    None,
+                    Some(bucket),
+                    amount,
+                    Some(reason),
+                ),
+                TxRecord::AUTHORIZE(metadata, amount) => (
+                    SnapshotTxType::Authorization,
+                    metadata,
+                    None,
+                    None,
+                    amount,
+                    None,
+                ),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "only deposits, withdrawals, transfers, adjustments and authorizations can be saved",
+                    ))
+                }
+            };
+            write_line(
+                &mut writer,
+                &SnapshotLine::Transaction {
+                    r#type,
+                    client: metadata.client_id,
+                    currency: metadata.currency,
+                    tx: metadata.tx_id,
+                    destination,
+                    bucket,
+                    amount,
+                    reason,
+                    disputed: flags.disputed,
+                    chargedback: flags.chargedback,
+                    shortfall: flags.shortfall,
+                    captured: flags.captured,
+                    voided: flags.voided,
+                    declined: flags.declined,
+                    policy: flags.withdrawal_dispute_policy,
+                },
+            )?;
+        }
+
+        writer.flush()
+    }
+
+    /// restores a snapshot written by [`TxProc::save`] into the given, empty, transaction store
+    pub fn load_with_store(
+        config: TxProcConfig,
+        transactions: S,
+        reader: impl Read,
+    ) -> Result<TxProc<S>, SnapshotError> {
+        let mut tx_proc = TxProc::with_store(config, transactions);
+
+        let mut lines = BufReader::new(reader).lines();
+
+        match lines.next() {
+            Some(line) => match parse_line(1, &line?)? {
+                SnapshotLine::Header { version } if version == SNAPSHOT_VERSION => {}
+                SnapshotLine::Header { version } => {
+                    return Err(SnapshotError::UnsupportedVersion(version))
+                }
+                _ => return Err(SnapshotError::MissingHeader),
+            },
+            None => return Err(SnapshotError::MissingHeader),
+        }
+
+        for (index, line) in lines.enumerate() {
+            let line_number = index as u64 + 2;
+            match parse_line(line_number, &line?)? {
+                SnapshotLine::Header { .. } => {
+                    return Err(SnapshotError::Invalid {
+                        line: line_number,
+                        message: "unexpected header".to_string(),
+                    })
+                }
+                SnapshotLine::Account {
+                    client,
+                    currency,
+                    available,
+                    held,
+                    locked,
+                    shortfall,
+                    adjustments,
+                    adjusted_amount,
+                    fees,
+                    authorized,
+                } => {
+                    tx_proc.account_summaries.insert(
+                        (client, currency),
+                        AccountSummary {
+                            currency,
+                            held_amount: held,
+                            available_amount: available,
+                            locked,
+                            credit_limit: dec!(0),
+                            shortfall,
+                            adjustments,
+                            adjusted_amount,
+                            fees,
+                            authorized_amount: authorized,
+                        },
+                    );
+                }
+                SnapshotLine::Transaction {
+                    r#type,
+                    client,
+                    currency,
+                    tx,
+                    destination,
+                    bucket,
+                    amount,
+                    reason,
+                    disputed,
+                    chargedback,
+                    shortfall,
+                    captured,
+                    voided,
+                    declined,
+                    policy,
+                } => {
+                    let metadata = TxRecordMetadata::new(client, tx).with_currency(currency);
+                    let record = match r#type {
+                        SnapshotTxType::Deposit => TxRecord::DEPOSIT(metadata, amount),
+                        SnapshotTxType::Withdrawal => TxRecord::WITHDRAWAL(metadata, amount),
+                        SnapshotTxType::Transfer => match destination {
+                            Some(destination) => TxRecord::TRANSFER(metadata, destination, amount),
+                            None => {
+                                return Err(SnapshotError::Invalid {
+                                    line: line_number,
+                                    message: format!("transfer {} without destination", tx),
+                                })
+                            }
+                        },
+                        SnapshotTxType::Adjustment => TxRecord::ADJUSTMENT(
+                            metadata,
+                            bucket.unwrap_or_default(),
+                            amount,
+                            reason.unwrap_or_default(),
+                        ),
+                        SnapshotTxType::Authorization => TxRecord::AUTHORIZE(metadata, amount),
+                    };
+                    // the dispute is resolved or charged back under the policy it was opened with
+                    if disputed && policy.is_none() && matches!(record, TxRecord::WITHDRAWAL(..)) {
+                        return Err(SnapshotError::Invalid {
+                            line: line_number,
+                            message: format!("disputed withdrawal {} without dispute policy", tx),
+                        });
+                    }
+                    if !tx_proc.transactions.insert_if_absent(record)? {
+                        return Err(SnapshotError::Invalid {
+                            line: line_number,
+                            message: format!("duplicated transaction {}", tx),
+                        });
+                    }
+                    let flags = TxRecordFlags {
+                        captured,
+                        voided,
+                        declined,
+                        withdrawal_dispute_policy: policy,
+                        ..TxRecordFlags::new(disputed, chargedback).with_shortfall(shortfall)
+                    };
+                    if flags != TxRecordFlags::default() {
+                        tx_proc.transactions.update_flags(tx, flags)?;
+                    }
+                }
+            }
+        }
+
+        Ok(tx_proc)
+    }
+}
+
+fn parse_line(line_number: u64, line: &str) -> Result<SnapshotLine, SnapshotError> {
+    serde_json::from_str(line).map_err(|e| SnapshotError::Invalid {
+        line: line_number,
+        message: e.to_string(),
+    })
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// the first line is not a snapshot header
+    MissingHeader,
+    /// the snapshot was written with an incompatible version
+    UnsupportedVersion(u32),
+    Invalid {
+        line: u64,
+        message: String,
+    },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "snapshot i/o error: {}", e),
+            SnapshotError::MissingHeader => write!(f, "snapshot header is missing"),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported snapshot version {}, expected {}",
+                version, SNAPSHOT_VERSION
+            ),
+            SnapshotError::Invalid { line, message } => {
+                write!(f, "invalid snapshot line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> SnapshotError {
+        SnapshotError::Io(e)
+    }
+}
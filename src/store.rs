@@ -0,0 +1,73 @@
+mod append_log;
+
+pub use append_log::{AppendLogStore, APPEND_LOG_VERSION};
+
+use crate::{TxRecord, TxRecordFlags};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io;
+
+/// Storage of the processed transactions, looked up by disputes, resolves and chargebacks
+///
+/// storage failures are reported as `io::Error`s and surfaced as [`crate::TxRejection::Storage`]
+pub trait TransactionStore {
+    fn get(&self, tx_id: u32) -> io::Result<Option<(TxRecord, TxRecordFlags)>>;
+
+    fn contains(&self, tx_id: u32) -> io::Result<bool> {
+        Ok(self.get(tx_id)?.is_some())
+    }
+
+    /// records the transaction with default flags,
+    /// returns `false` without modifying the store if the transaction id is already recorded
+    fn insert_if_absent(&mut self, record: TxRecord) -> io::Result<bool>;
+
+    fn update_flags(&mut self, tx_id: u32, flags: TxRecordFlags) -> io::Result<()>;
+
+    /// every recorded transaction with its latest flags, in no particular order
+    fn iter(&self) -> Box<dyn Iterator<Item = io::Result<(TxRecord, TxRecordFlags)>> + '_>;
+}
+
+/// Default [`TransactionStore`], keeping every transaction in memory
+///
+/// it is possible to saturate the memory if too many transactions are processed
+#[derive(Default)]
+pub struct InMemoryStore {
+    transactions: HashMap<u32, (TxRecord, TxRecordFlags)>,
+}
+
+impl TransactionStore for InMemoryStore {
+    fn get(&self, tx_id: u32) -> io::Result<Option<(TxRecord, TxRecordFlags)>> {
+        Ok(self.transactions.get(&tx_id).cloned())
+    }
+
+    fn contains(&self, tx_id: u32) -> io::Result<bool> {
+        Ok(self.transactions.contains_key(&tx_id))
+    }
+
+    fn insert_if_absent(&mut self, record: TxRecord) -> io::Result<bool> {
+        match self.transactions.entry(record.record_metadata().tx_id()) {
+            Entry::Occupied(_) => Ok(false),
+            Entry::Vacant(entry) => {
+                entry.insert((record, TxRecordFlags::default()));
+                Ok(true)
+            }
+        }
+    }
+
+    fn update_flags(&mut self, tx_id: u32, flags: TxRecordFlags) -> io::Result<()> {
+        match self.transactions.get_mut(&tx_id) {
+            Some((_, tx_record_flags)) => {
+                *tx_record_flags = flags;
+                Ok(())
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("transaction {} not found", tx_id),
+            )),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = io::Result<(TxRecord, TxRecordFlags)>> + '_> {
+        Box::new(self.transactions.values().cloned().map(Ok))
+    }
+}
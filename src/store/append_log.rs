@@ -0,0 +1,334 @@
+use crate::store::TransactionStore;
+use crate::{Bucket, Currency, TxRecord, TxRecordFlags, TxRecordMetadata, WithdrawalDisputePolicy};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// version written in the header of the transaction logs, bumped on incompatible format changes
+pub const APPEND_LOG_VERSION: u32 = 1;
+
+// the log starts with a header: the magic bytes, then the version (4, little endian)
+const MAGIC: [u8; 4] = *b"TXLG";
+const HEADER_SIZE: usize = 8;
+
+// tag (1) + client id (2) + tx id (4) + amount (16) + flags (1) + currency (3) + shortfall (16)
+// + transfer destination or adjustment bucket (2)
+//
+// the reason of the adjustments is not kept, it is part of the journal
+const ENTRY_SIZE: usize = 45;
+
+const DEPOSIT_TAG: u8 = 0;
+const WITHDRAWAL_TAG: u8 = 1;
+const TRANSFER_TAG: u8 = 2;
+const ADJUSTMENT_TAG: u8 = 3;
+const AUTHORIZATION_TAG: u8 = 4;
+
+const HELD_BUCKET: u16 = 1;
+
+const DISPUTED_FLAG: u8 = 1;
+const CHARGEDBACK_FLAG: u8 = 1 << 1;
+const CAPTURED_FLAG: u8 = 1 << 2;
+const VOIDED_FLAG: u8 = 1 << 3;
+const DECLINED_FLAG: u8 = 1 << 4;
+// withdrawal dispute policy of a disputed withdrawal
+const HOLD_REVERSAL_FLAG: u8 = 1 << 5;
+const PROVISIONAL_CREDIT_FLAG: u8 = 1 << 6;
+
+/// [`TransactionStore`] keeping transactions in a local append-only file
+///
+/// only the offset of the latest entry of each transaction is kept in memory,
+/// a flag update appends a new entry for the transaction instead of rewriting the previous one
+pub struct AppendLogStore {
+    file: File,
+    len: u64,
+    offsets: HashMap<u32, u64>,
+}
+
+impl AppendLogStore {
+    /// creates the log file, truncating it if it already exists
+    pub fn create(path: impl AsRef<Path>) -> io::Result<AppendLogStore> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        file.write_all(&header())?;
+
+        Ok(AppendLogStore {
+            file,
+            len: HEADER_SIZE as u64,
+            offsets: HashMap::new(),
+        })
+    }
+
+    /// opens an existing log file, or creates it, and rebuilds the index from its entries
+    ///
+    /// a file without the header of the current [`APPEND_LOG_VERSION`] is refused
+    pub fn open(path: impl AsRef<Path>) -> io::Result<AppendLogStore> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        if file.metadata()?.len() == 0 {
+            file.write_all(&header())?;
+            file.seek(SeekFrom::Start(0))?;
+        }
+
+        let mut reader = BufReader::new(&file);
+
+        let mut header = [0; HEADER_SIZE];
+        match reader.read_exact(&mut header) {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "transaction log header is missing",
+                ))
+            }
+            Err(e) => return Err(e),
+        }
+        if header[..4] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "transaction log header is missing",
+            ));
+        }
+        let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if version != APPEND_LOG_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "unsupported transaction log version {}, expected {}",
+                    version, APPEND_LOG_VERSION
+                ),
+            ));
+        }
+
+        let mut offsets = HashMap::new();
+        let mut len = HEADER_SIZE as u64;
+        let mut entry = [0; ENTRY_SIZE];
+        loop {
+            match reader.read_exact(&mut entry) {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+            let (record, _) = decode_entry(&entry)?;
+            offsets.insert(record.record_metadata().tx_id(), len);
+            len += ENTRY_SIZE as u64;
+        }
+
+        if file.metadata()?.len() != len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated entry at the end of the transaction log",
+            ));
+        }
+
+        Ok(AppendLogStore { file, len, offsets })
+    }
+
+    fn append(&mut self, record: &TxRecord, flags: TxRecordFlags) -> io::Result<()> {
+        let entry = encode_entry(record, flags)?;
+
+        // the file may have been opened in append mode, in which case seeking is a no-op
+        self.file.seek(SeekFrom::Start(self.len))?;
+        self.file.write_all(&entry)?;
+
+        self.offsets
+            .insert(record.record_metadata().tx_id(), self.len);
+        self.len += ENTRY_SIZE as u64;
+
+        Ok(())
+    }
+}
+
+impl TransactionStore for AppendLogStore {
+    fn get(&self, tx_id: u32) -> io::Result<Option<(TxRecord, TxRecordFlags)>> {
+        let offset = match self.offsets.get(&tx_id) {
+            Some(offset) => *offset,
+            None => return Ok(None),
+        };
+
+        let mut file = &self.file;
+        let mut entry = [0; ENTRY_SIZE];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut entry)?;
+
+        decode_entry(&entry).map(Some)
+    }
+
+    fn contains(&self, tx_id: u32) -> io::Result<bool> {
+        Ok(self.offsets.contains_key(&tx_id))
+    }
+
+    fn insert_if_absent(&mut self, record: TxRecord) -> io::Result<bool> {
+        if self.offsets.contains_key(&record.record_metadata().tx_id()) {
+            return Ok(false);
+        }
+
+        self.append(&record, TxRecordFlags::default())?;
+
+        Ok(true)
+    }
+
+    fn update_flags(&mut self, tx_id: u32, flags: TxRecordFlags) -> io::Result<()> {
+        match self.get(tx_id)? {
+            Some((record, _)) => self.append(&record, flags),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("transaction {} not found", tx_id),
+            )),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = io::Result<(TxRecord, TxRecordFlags)>> + '_> {
+        Box::new(self.offsets.keys().map(|tx_id| {
+            self.get(*tx_id)?.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "indexed transaction not found")
+            })
+        }))
+    }
+}
+
+fn header() -> [u8; HEADER_SIZE] {
+    let mut header = [0; HEADER_SIZE];
+    header[..4].copy_from_slice(&MAGIC);
+    header[4..].copy_from_slice(&APPEND_LOG_VERSION.to_le_bytes());
+    header
+}
+
+fn encode_entry(record: &TxRecord, flags: TxRecordFlags) -> io::Result<[u8; ENTRY_SIZE]> {
+    let (tag, metadata, amount, destination) = match record {
+        TxRecord::DEPOSIT(metadata, amount) => (DEPOSIT_TAG, metadata, amount, 0),
+        TxRecord::WITHDRAWAL(metadata, amount) => (WITHDRAWAL_TAG, metadata, amount, 0),
+        TxRecord::TRANSFER(metadata, destination, amount) => {
+            (TRANSFER_TAG, metadata, amount, *destination)
+        }
+        TxRecord::ADJUSTMENT(metadata, bucket, amount, _) => {
+            let bucket = match bucket {
+                Bucket::Available => 0,
+                Bucket::Held => HELD_BUCKET,
+            };
+            (ADJUSTMENT_TAG, metadata, amount, bucket)
+        }
+        TxRecord::AUTHORIZE(metadata, amount) => (AUTHORIZATION_TAG, metadata, amount, 0),
+        _ => return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "only deposits, withdrawals, transfers, adjustments and authorizations can be stored",
+        )),
+    };
+
+    let mut entry = [0; ENTRY_SIZE];
+    entry[0] = tag;
+    entry[1..3].copy_from_slice(&metadata.client_id().to_le_bytes());
+    entry[3..7].copy_from_slice(&metadata.tx_id().to_le_bytes());
+    entry[7..23].copy_from_slice(&amount.serialize());
+    if flags.is_disputed() {
+        entry[23] |= DISPUTED_FLAG;
+    }
+    if flags.is_chargedback() {
+        entry[23] |= CHARGEDBACK_FLAG;
+    }
+    if flags.is_captured() {
+        entry[23] |= CAPTURED_FLAG;
+    }
+    if flags.is_voided() {
+        entry[23] |= VOIDED_FLAG;
+    }
+    if flags.is_declined() {
+        entry[23] |= DECLINED_FLAG;
+    }
+    match flags.withdrawal_dispute_policy() {
+        Some(WithdrawalDisputePolicy::HoldReversal) => entry[23] |= HOLD_REVERSAL_FLAG,
+        Some(WithdrawalDisputePolicy::ProvisionalCredit) => entry[23] |= PROVISIONAL_CREDIT_FLAG,
+        Some(WithdrawalDisputePolicy::Reject) | None => {}
+    }
+    entry[24..27].copy_from_slice(&metadata.currency().to_bytes());
+    entry[27..43].copy_from_slice(&flags.shortfall().serialize());
+    entry[43..45].copy_from_slice(&destination.to_le_bytes());
+
+    Ok(entry)
+}
+
+fn decode_entry(entry: &[u8; ENTRY_SIZE]) -> io::Result<(TxRecord, TxRecordFlags)> {
+    let currency = Currency::from_bytes([entry[24], entry[25], entry[26]]).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid currency in the transaction log",
+        )
+    })?;
+    let metadata = TxRecordMetadata::new(
+        u16::from_le_bytes([entry[1], entry[2]]),
+        u32::from_le_bytes([entry[3], entry[4], entry[5], entry[6]]),
+    )
+    .with_currency(currency);
+
+    let mut amount = [0; 16];
+    amount.copy_from_slice(&entry[7..23]);
+    let amount = Decimal::deserialize(amount);
+
+    let record = match entry[0] {
+        DEPOSIT_TAG => TxRecord::DEPOSIT(metadata, amount),
+        WITHDRAWAL_TAG => TxRecord::WITHDRAWAL(metadata, amount),
+        TRANSFER_TAG => {
+            TxRecord::TRANSFER(metadata, u16::from_le_bytes([entry[43], entry[44]]), amount)
+        }
+        ADJUSTMENT_TAG => {
+            let bucket = match u16::from_le_bytes([entry[43], entry[44]]) {
+                HELD_BUCKET => Bucket::Held,
+                _ => Bucket::Available,
+            };
+            TxRecord::ADJUSTMENT(metadata, bucket, amount, String::new())
+        }
+        AUTHORIZATION_TAG => TxRecord::AUTHORIZE(metadata, amount),
+        tag => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown entry tag {} in the transaction log", tag),
+            ))
+        }
+    };
+
+    let mut shortfall = [0; 16];
+    shortfall.copy_from_slice(&entry[27..43]);
+
+    let mut flags = TxRecordFlags::new(
+        entry[23] & DISPUTED_FLAG != 0,
+        entry[23] & CHARGEDBACK_FLAG != 0,
+    )
+    .with_shortfall(Decimal::deserialize(shortfall));
+    if entry[23] & CAPTURED_FLAG != 0 {
+        flags = flags.with_captured();
+    }
+    if entry[23] & VOIDED_FLAG != 0 {
+        flags = flags.with_voided();
+    }
+    if entry[23] & DECLINED_FLAG != 0 {
+        flags = flags.with_declined();
+    }
+    if entry[23] & HOLD_REVERSAL_FLAG != 0 {
+        flags = flags.with_withdrawal_dispute_policy(Some(WithdrawalDisputePolicy::HoldReversal));
+    }
+    if entry[23] & PROVISIONAL_CREDIT_FLAG != 0 {
+        flags =
+            flags.with_withdrawal_dispute_policy(Some(WithdrawalDisputePolicy::ProvisionalCredit));
+    }
+    if flags.is_disputed()
+        && flags.withdrawal_dispute_policy().is_none()
+        && matches!(record, TxRecord::WITHDRAWAL(..))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "disputed withdrawal without dispute policy in the transaction log",
+        ));
+    }
+
+    Ok((record, flags))
+}
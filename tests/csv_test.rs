@@ -0,0 +1,327 @@
+use rust_decimal_macros::dec;
+use tx_proc::csv::{AccountSummaryWriter, CsvLineErrorKind, CsvOptions, TxRecordReader};
+use tx_proc::*;
+
+#[test]
+fn raw_records() {
+    // the raw record is the line as read, with its delimiter and quotes
+    let input = "type;client;tx;amount\n\
+                 deposit;1;1;\"1,5\"\n\
+                 deposit; 1; 2\n\
+                 deposit;1;3;2.0\r\n";
+    let options = CsvOptions {
+        delimiter: b';',
+        ..CsvOptions::default()
+    };
+    let mut reader = TxRecordReader::with_options(input.as_bytes(), &options);
+
+    assert!(reader.next().expect("expected a record").is_err());
+    assert_eq!(reader.raw_record(), "deposit;1;1;\"1,5\"");
+    assert!(reader.next().expect("expected a record").is_err());
+    assert_eq!(reader.raw_record(), "deposit; 1; 2");
+    assert!(reader.next().expect("expected a record").is_ok());
+    assert_eq!(reader.raw_record(), "deposit;1;3;2.0");
+
+    // across the reads of the underlying buffer
+    let input: String = std::iter::once("type, client, tx, amount\n".to_string())
+        .chain((1..=2000).map(|tx| format!("deposit, 1, {tx}, {tx}.5\n")))
+        .collect();
+    let mut reader = TxRecordReader::new(input.as_bytes());
+    for tx in 1..=2000 {
+        assert!(reader.next().expect("expected a record").is_ok());
+        assert_eq!(reader.raw_record(), format!("deposit, 1, {tx}, {tx}.5"));
+    }
+}
+
+#[test]
+fn read_records() {
+    let input = "type, client, tx, amount\n\
+                 deposit, 1, 1, 1.5\n\
+                 invalid_type, 1, 2, 1.0\n\
+                 withdrawal, 1, 3, e\n\
+                 dispute, 1, 1, 222\n\
+                 deposit, 1, 4,\n\
+                 resolve, 1, 1,\n";
+
+    let records: Vec<_> = TxRecordReader::new(input.as_bytes()).collect();
+
+    assert_eq!(records.len(), 6);
+
+    match &records[0] {
+        Ok(TxRecord::DEPOSIT(metadata, amount)) => {
+            assert_eq!(metadata.client_id(), 1);
+            assert_eq!(metadata.tx_id(), 1);
+            assert_eq!(*amount, dec!(1.5));
+        }
+        _ => panic!("expected a deposit"),
+    }
+
+    // unknown type, the line number is reported
+    let error = records[1].as_ref().expect_err("expected an error");
+    assert!(matches!(error.kind(), CsvLineErrorKind::UnknownType(t) if t == "invalid_type"));
+    assert_eq!(error.line(), Some(3));
+
+    // unparsable amount
+    let error = records[2].as_ref().expect_err("expected an error");
+    assert!(matches!(error.kind(), CsvLineErrorKind::Csv(_)));
+    assert_eq!(error.line(), Some(4));
+
+    // the extraneous amount is discarded
+    assert!(matches!(&records[3], Ok(TxRecord::DISPUTE(_))));
+
+    // the amount is required on deposits and withdrawals only
+    let error = records[4].as_ref().expect_err("expected an error");
+    assert!(matches!(error.kind(), CsvLineErrorKind::MissingAmount));
+    assert_eq!(error.code(), "missing_amount");
+    assert!(matches!(&records[5], Ok(TxRecord::RESOLVE(_))));
+}
+
+#[test]
+fn read_records_with_options() {
+    let deposit = |record: &Result<TxRecord, _>| match record {
+        Ok(TxRecord::DEPOSIT(metadata, amount)) => {
+            (metadata.client_id(), metadata.tx_id(), *amount)
+        }
+        _ => panic!("expected a deposit"),
+    };
+
+    // no header line, semicolons
+    let options = CsvOptions {
+        has_headers: false,
+        delimiter: b';',
+        ..CsvOptions::default()
+    };
+    let records: Vec<_> =
+        TxRecordReader::with_options("deposit; 1; 2; 1.5\n".as_bytes(), &options).collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!(deposit(&records[0]), (1, 2, dec!(1.5)));
+
+    // reordered columns, without header line
+    let options = CsvOptions {
+        has_headers: false,
+        columns: Some(vec![
+            "tx".into(),
+            "client".into(),
+            "type".into(),
+            "amount".into(),
+        ]),
+        delimiter: b'\t',
+    };
+    let records: Vec<_> =
+        TxRecordReader::with_options("2\t1\tdeposit\t1.5\n".as_bytes(), &options).collect();
+    assert_eq!(deposit(&records[0]), (1, 2, dec!(1.5)));
+
+    // renamed columns, the header line is skipped, the unknown column is ignored
+    let options = CsvOptions {
+        columns: Some(vec![
+            "tx".into(),
+            "client".into(),
+            "comment".into(),
+            "type".into(),
+            "amount".into(),
+        ]),
+        ..CsvOptions::default()
+    };
+    let input = "id,customer,comment,kind,value\n2,1,first,deposit,1.5\n";
+    let mut reader = TxRecordReader::with_options(input.as_bytes(), &options);
+    assert_eq!(
+        deposit(&reader.next().expect("expected a record")),
+        (1, 2, dec!(1.5))
+    );
+    assert_eq!(reader.line(), Some(2));
+    assert!(reader.next().is_none());
+
+    // reordered columns are matched by name with the header line
+    let input = "tx, amount, client, type\n2, 1.5, 1, deposit\n";
+    let records: Vec<_> = TxRecordReader::new(input.as_bytes()).collect();
+    assert_eq!(deposit(&records[0]), (1, 2, dec!(1.5)));
+
+    let options = CsvOptions {
+        columns: Some(vec!["type".into(), "client".into(), "amount".into()]),
+        ..CsvOptions::default()
+    };
+    assert_eq!(options.validate(), Err("missing column: tx".to_string()));
+    assert_eq!(CsvOptions::default().validate(), Ok(()));
+}
+
+#[test]
+fn currency_column() {
+    let input = "type, client, tx, amount, currency\n\
+                 deposit, 1, 1, 1.5, eur\n\
+                 deposit, 1, 2, 1.5,\n\
+                 withdrawal, 1, 3, 1.5, EURO\n\
+                 dispute, 1, 1, , USD\n";
+
+    let records: Vec<_> = TxRecordReader::new(input.as_bytes()).collect();
+
+    let eur = Currency::new("EUR").expect("valid currency");
+    assert!(
+        matches!(&records[0], Ok(TxRecord::DEPOSIT(metadata, _)) if metadata.currency() == eur)
+    );
+    assert!(
+        matches!(&records[1], Ok(TxRecord::DEPOSIT(metadata, _)) if metadata.currency().is_default())
+    );
+    let error = records[2].as_ref().expect_err("expected an error");
+    assert!(matches!(error.kind(), CsvLineErrorKind::InvalidCurrency(code) if code == "EURO"));
+    assert_eq!(error.code(), "invalid_currency");
+    // the currency of a dispute is discarded
+    assert_eq!(
+        records[3].as_ref().expect("valid dispute"),
+        &TxRecord::DISPUTE(TxRecordMetadata::new(1, 1))
+    );
+
+    let proc = &mut TxProc::default();
+    for record in records.into_iter().flatten() {
+        proc.submit_tx_record(record).expect("valid record");
+    }
+
+    let mut output = Vec::new();
+    let mut writer = AccountSummaryWriter::new(&mut output).with_currency();
+    for (client_id, summary) in proc.summary_iterator() {
+        writer.write(*client_id, summary).expect("valid summary");
+    }
+    writer.flush().expect("flush to memory");
+    drop(writer);
+
+    assert_eq!(
+        String::from_utf8(output).expect("valid UTF-8"),
+        "client,currency,available,held,total,locked\n1,,1.5,0,1.5,false\n1,EUR,0.0,1.5,1.5,false\n"
+    );
+}
+
+#[test]
+fn destination_column() {
+    let input = "type, client, tx, amount, destination\n\
+                 transfer, 1, 1, 1.5, 2\n\
+                 transfer, 1, 2, 1.5,\n\
+                 deposit, 1, 3, 1.5, 2\n";
+
+    let records: Vec<_> = TxRecordReader::new(input.as_bytes()).collect();
+
+    assert_eq!(
+        records[0].as_ref().expect("valid transfer"),
+        &TxRecord::TRANSFER(TxRecordMetadata::new(1, 1), 2, dec!(1.5))
+    );
+    let error = records[1].as_ref().expect_err("expected an error");
+    assert!(matches!(error.kind(), CsvLineErrorKind::MissingDestination));
+    assert_eq!(error.code(), "missing_destination");
+    assert_eq!(error.to_string(), "missing destination on line 3");
+    // the destination of a deposit is discarded
+    assert_eq!(
+        records[2].as_ref().expect("valid deposit"),
+        &TxRecord::DEPOSIT(TxRecordMetadata::new(1, 3), dec!(1.5))
+    );
+}
+
+#[test]
+fn authorization_records() {
+    let input = "type, client, tx, amount\n\
+                 authorize, 1, 1, 2.5\n\
+                 capture, 1, 1,\n\
+                 void, 1, 1, 3\n\
+                 authorize, 1, 2,\n";
+
+    let records: Vec<_> = TxRecordReader::new(input.as_bytes()).collect();
+    assert_eq!(
+        records[0].as_ref().expect("valid authorization"),
+        &TxRecord::AUTHORIZE(TxRecordMetadata::new(1, 1), dec!(2.5))
+    );
+    assert_eq!(
+        records[1].as_ref().expect("valid capture"),
+        &TxRecord::CAPTURE(TxRecordMetadata::new(1, 1))
+    );
+    // the extraneous amount is discarded
+    assert_eq!(
+        records[2].as_ref().expect("valid void"),
+        &TxRecord::VOID(TxRecordMetadata::new(1, 1))
+    );
+    let error = records[3].as_ref().expect_err("expected an error");
+    assert_eq!(error.code(), "missing_amount");
+}
+
+#[test]
+fn admin_records() {
+    let input = "type, client, tx, amount, reason\n\
+                 unlock, 1, 1, , ticket 42\n\
+                 unlock, 1, 2, ,\n";
+
+    let records: Vec<_> = TxRecordReader::new(input.as_bytes()).collect();
+    assert_eq!(
+        records[0].as_ref().expect("valid unlock"),
+        &TxRecord::UNLOCK(TxRecordMetadata::new(1, 1), "ticket 42".to_string())
+    );
+    let error = records[1].as_ref().expect_err("expected an error");
+    assert_eq!(error.code(), "missing_reason");
+
+    // end-user inputs
+    let records: Vec<_> = TxRecordReader::new(input.as_bytes())
+        .without_admin_records()
+        .collect();
+    let error = records[0].as_ref().expect_err("expected an error");
+    assert!(matches!(error.kind(), CsvLineErrorKind::AdminRecord(t) if t == "unlock"));
+    assert_eq!(
+        error.to_string(),
+        "admin record not accepted on line 2: unlock"
+    );
+}
+
+#[test]
+fn adjustment_records() {
+    let input = "type, client, tx, amount, reason, bucket\n\
+                 adjustment, 1, 1, -1.5, incident 7,\n\
+                 adjustment, 1, 2, 2, incident 7, held\n\
+                 adjustment, 1, 3, 2, incident 7, pending\n\
+                 adjustment, 1, 4, 2, ,\n";
+
+    let records: Vec<_> = TxRecordReader::new(input.as_bytes()).collect();
+    assert_eq!(
+        records[0].as_ref().expect("valid adjustment"),
+        &TxRecord::ADJUSTMENT(
+            TxRecordMetadata::new(1, 1),
+            Bucket::Available,
+            dec!(-1.5),
+            "incident 7".to_string()
+        )
+    );
+    assert_eq!(
+        records[1].as_ref().expect("valid adjustment"),
+        &TxRecord::ADJUSTMENT(
+            TxRecordMetadata::new(1, 2),
+            Bucket::Held,
+            dec!(2),
+            "incident 7".to_string()
+        )
+    );
+    let error = records[2].as_ref().expect_err("expected an error");
+    assert_eq!(error.code(), "invalid_bucket");
+    assert_eq!(error.to_string(), "invalid bucket on line 4: pending");
+    let error = records[3].as_ref().expect_err("expected an error");
+    assert_eq!(error.code(), "missing_reason");
+
+    let records: Vec<_> = TxRecordReader::new(input.as_bytes())
+        .without_admin_records()
+        .collect();
+    let error = records[0].as_ref().expect_err("expected an error");
+    assert!(matches!(error.kind(), CsvLineErrorKind::AdminRecord(t) if t == "adjustment"));
+}
+
+#[test]
+fn write_summaries() {
+    let proc = &mut TxProc::default();
+
+    proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(1.5)))
+        .expect("valid deposit");
+
+    let mut output = Vec::new();
+    let mut writer = AccountSummaryWriter::new(&mut output);
+    for (client_id, summary) in proc.summary_iterator() {
+        writer.write(*client_id, summary).expect("valid summary");
+    }
+    writer.flush().expect("flush to memory");
+    drop(writer);
+
+    assert_eq!(
+        String::from_utf8(output).expect("valid UTF-8"),
+        "client,available,held,total,locked\n1,1.5,0,1.5,false\n"
+    );
+}
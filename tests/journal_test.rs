@@ -0,0 +1,402 @@
+use rust_decimal_macros::dec;
+use tx_proc::journal::{Balances, JournalError, JournalEvent, JournalReader, JournalWriter};
+use tx_proc::store::TransactionStore;
+use tx_proc::*;
+
+fn summaries<S: TransactionStore>(proc: &TxProc<S>) -> Vec<String> {
+    let mut summaries: Vec<String> = proc
+        .summary_iterator()
+        .map(|(client_id, summary)| {
+            format!(
+                "{client_id},{},{},{},{}",
+                summary.available_amount(),
+                summary.held_amount(),
+                summary.total_amount(),
+                summary.is_locked()
+            )
+        })
+        .collect();
+    summaries.sort();
+    summaries
+}
+
+fn journaled() -> TxProc {
+    TxProc::with_config(TxProcConfig {
+        journal: true,
+        ..TxProcConfig::default()
+    })
+}
+
+#[test]
+fn journal_events() {
+    let proc = &mut journaled();
+
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(3))),
+        Ok(Applied::Deposited)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(1, 1))),
+        Ok(Applied::Disputed)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(1, 1))),
+        Ok(Applied::ChargedBack)
+    );
+
+    // rejected record for a new client
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(TxRecordMetadata::new(2, 2), dec!(3))),
+        Err(TxRejection::InsufficientFunds)
+    );
+
+    assert_eq!(
+        proc.drain_journal().collect::<Vec<_>>(),
+        vec![
+            JournalEvent::AccountOpened {
+                client: 1,
+                currency: Currency::default(),
+            },
+            JournalEvent::FundsDeposited {
+                client: 1,
+                currency: Currency::default(),
+                tx: 1,
+                amount: dec!(3),
+                before: Balances::default(),
+                after: Balances {
+                    available: dec!(3),
+                    held: dec!(0)
+                },
+            },
+            JournalEvent::FundsHeld {
+                client: 1,
+                currency: Currency::default(),
+                tx: 1,
+                shortfall: dec!(0),
+                policy: None,
+                before: Balances {
+                    available: dec!(3),
+                    held: dec!(0)
+                },
+                after: Balances {
+                    available: dec!(0),
+                    held: dec!(3)
+                },
+            },
+            JournalEvent::FundsChargedBack {
+                client: 1,
+                currency: Currency::default(),
+                tx: 1,
+                before: Balances {
+                    available: dec!(0),
+                    held: dec!(3)
+                },
+                after: Balances::default(),
+            },
+            JournalEvent::AccountLocked {
+                client: 1,
+                currency: Currency::default(),
+            },
+            // the account is opened even though the withdrawal is rejected
+            JournalEvent::AccountOpened {
+                client: 2,
+                currency: Currency::default(),
+            },
+            // the declined withdrawal keeps its transaction id
+            JournalEvent::WithdrawalDeclined {
+                client: 2,
+                currency: Currency::default(),
+                tx: 2,
+                amount: dec!(3),
+            },
+        ]
+    );
+
+    // the journal has been drained
+    assert_eq!(proc.drain_journal().count(), 0);
+}
+
+#[test]
+fn replay_journal() {
+    let eur = Currency::new("EUR").expect("valid currency");
+    let proc = &mut journaled();
+
+    for record in [
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(3)),
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 2), dec!(2)),
+        TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 3), dec!(1)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(1, 1)),
+        TxRecord::RESOLVE(TxRecordMetadata::new(1, 1)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(1, 2)),
+        TxRecord::DEPOSIT(TxRecordMetadata::new(2, 4), dec!(1)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(2, 4)),
+        TxRecord::CHARGEBACK(TxRecordMetadata::new(2, 4)),
+        TxRecord::DEPOSIT(TxRecordMetadata::new(3, 5), dec!(-1)),
+        TxRecord::DEPOSIT(TxRecordMetadata::new(3, 6).with_currency(eur), dec!(2)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(3, 6)),
+        TxRecord::TRANSFER(TxRecordMetadata::new(1, 7), 4, dec!(1)),
+        TxRecord::DEPOSIT(TxRecordMetadata::new(5, 8), dec!(2)),
+        TxRecord::TRANSFER(TxRecordMetadata::new(5, 9), 6, dec!(2)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(6, 9)),
+        TxRecord::CHARGEBACK(TxRecordMetadata::new(6, 9)),
+        TxRecord::UNLOCK(TxRecordMetadata::new(6, 10), "ticket 42".to_string()),
+        TxRecord::ADJUSTMENT(
+            TxRecordMetadata::new(2, 11),
+            Bucket::Held,
+            dec!(0.5),
+            "incident 7".to_string(),
+        ),
+        TxRecord::DEPOSIT(TxRecordMetadata::new(7, 12), dec!(5)),
+        TxRecord::AUTHORIZE(TxRecordMetadata::new(7, 13), dec!(2)),
+        TxRecord::CAPTURE(TxRecordMetadata::new(7, 13)),
+        TxRecord::AUTHORIZE(TxRecordMetadata::new(7, 14), dec!(1)),
+        TxRecord::VOID(TxRecordMetadata::new(7, 14)),
+        TxRecord::AUTHORIZE(TxRecordMetadata::new(7, 15), dec!(1)),
+        TxRecord::WITHDRAWAL(TxRecordMetadata::new(7, 16), dec!(10)),
+        TxRecord::TRANSFER(TxRecordMetadata::new(7, 17), 8, dec!(10)),
+        TxRecord::AUTHORIZE(TxRecordMetadata::new(7, 18), dec!(10)),
+    ] {
+        let _ = proc.submit_tx_record(record);
+    }
+
+    // the journal goes through its JSON lines encoding
+    let mut journal = Vec::new();
+    let mut writer = JournalWriter::new(&mut journal);
+    for event in proc.drain_journal() {
+        writer.write(&event).expect("failed to write event");
+    }
+
+    let replayed = &mut TxProc::replay(
+        JournalReader::new(journal.as_slice()).map(|event| event.expect("valid event")),
+    )
+    .expect("failed to replay");
+
+    assert_eq!(summaries(proc), summaries(replayed));
+    // the currency is only written for the other currencies
+    let journal = String::from_utf8(journal).expect("valid UTF-8");
+    assert!(
+        journal.contains("{\"event\":\"funds_held\",\"client\":3,\"currency\":\"EUR\",\"tx\":6,")
+    );
+    assert!(journal.contains("{\"event\":\"account_opened\",\"client\":1}"));
+    // the destination account is opened by the transfer
+    assert!(journal.contains("{\"event\":\"account_opened\",\"client\":4}"));
+    assert!(journal.contains("{\"event\":\"funds_returned\",\"client\":5,\"tx\":9,"));
+    assert!(journal.contains(
+        "{\"event\":\"account_unlocked\",\"client\":6,\"tx\":10,\"reason\":\"ticket 42\"}"
+    ));
+    assert!(
+        journal.contains("{\"event\":\"funds_captured\",\"client\":7,\"tx\":13,\"amount\":\"2\",")
+    );
+    assert!(journal
+        .contains("{\"event\":\"withdrawal_declined\",\"client\":7,\"tx\":16,\"amount\":\"10\"}"));
+    assert!(journal.contains(
+        "{\"event\":\"transfer_declined\",\"client\":7,\"tx\":17,\"destination\":8,\"amount\":\"10\"}"
+    ));
+    assert!(journal.contains(
+        "{\"event\":\"authorization_declined\",\"client\":7,\"tx\":18,\"amount\":\"10\"}"
+    ));
+    assert!(journal.contains(
+        "{\"event\":\"funds_adjusted\",\"client\":2,\"tx\":11,\"bucket\":\"held\",\"amount\":\"0.5\",\"reason\":\"incident 7\","
+    ));
+
+    // the replayed transactions and flags behave like the original ones
+    for proc in [proc, replayed] {
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 3), dec!(1))),
+            Err(TxRejection::DuplicateTransaction)
+        );
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(1, 2))),
+            Ok(Applied::Resolved)
+        );
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(2, 6), dec!(1))),
+            Err(TxRejection::AccountLocked)
+        );
+        assert_eq!(
+            proc.account_summary(6).map(|summary| summary.is_locked()),
+            Some(false)
+        );
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(4, 11), dec!(1))),
+            Err(TxRejection::DuplicateTransaction)
+        );
+        assert_eq!(
+            proc.account_summary(2)
+                .map(|summary| (summary.adjustments(), summary.adjusted_amount())),
+            Some((1, dec!(0.5)))
+        );
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(7, 16), dec!(1))),
+            Err(TxRejection::DuplicateTransaction)
+        );
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::VOID(TxRecordMetadata::new(7, 13))),
+            Err(TxRejection::AuthorizationClosed)
+        );
+        // the declined transfer and authorization keep their transaction id
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(7, 17), dec!(1))),
+            Err(TxRejection::DuplicateTransaction)
+        );
+        assert!(proc.account_summary(8).is_none());
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::CAPTURE(TxRecordMetadata::new(7, 18))),
+            Err(TxRejection::AuthorizationClosed)
+        );
+        assert_eq!(
+            proc.account_summary(7)
+                .map(|summary| (summary.held_amount(), summary.authorized_amount())),
+            Some((dec!(1), dec!(1)))
+        );
+        assert_eq!(
+            proc.account_summary_in(3, eur)
+                .expect("eur account")
+                .held_amount(),
+            dec!(2)
+        );
+    }
+}
+
+#[test]
+fn replay_shortfall() {
+    let proc = &mut TxProc::with_config(TxProcConfig {
+        journal: true,
+        deposit_dispute_policy: DepositDisputePolicy::HoldAvailable,
+        ..TxProcConfig::default()
+    });
+
+    for record in [
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(5)),
+        TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 2), dec!(3)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(1, 1)),
+    ] {
+        assert!(proc.submit_tx_record(record).is_ok());
+    }
+
+    let events: Vec<_> = proc.drain_journal().collect();
+    assert!(matches!(
+        events.last(),
+        Some(JournalEvent::FundsHeld { shortfall, .. }) if *shortfall == dec!(3)
+    ));
+
+    let replayed = &mut TxProc::replay(events).expect("failed to replay");
+    assert_eq!(
+        replayed.account_summary(1).map(|s| s.shortfall()),
+        Some(dec!(3))
+    );
+
+    // the shortfall recorded on the replayed transaction is debited by the chargeback
+    assert_eq!(
+        replayed.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(1, 1))),
+        Ok(Applied::ChargedBack)
+    );
+    let summary = replayed.account_summary(1).expect("summary");
+    assert_eq!(summary.available_amount(), dec!(-3));
+    assert_eq!(summary.held_amount(), dec!(0));
+    assert_eq!(summary.shortfall(), dec!(0));
+}
+
+#[test]
+fn replay_fees() {
+    let proc = &mut TxProc::with_config(TxProcConfig {
+        journal: true,
+        fees: FeeSchedule {
+            house_client: 9,
+            withdrawal_flat: dec!(0.5),
+            chargeback: dec!(1),
+            ..FeeSchedule::default()
+        },
+        ..TxProcConfig::default()
+    });
+
+    for record in [
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(5)),
+        TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 2), dec!(2)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(1, 1)),
+        TxRecord::CHARGEBACK(TxRecordMetadata::new(1, 1)),
+    ] {
+        assert!(proc.submit_tx_record(record).is_ok());
+    }
+
+    // the fee follows the movement of the record, the house account is opened by the first fee
+    let events: Vec<_> = proc.drain_journal().collect();
+    assert!(matches!(
+        &events[2..5],
+        [
+            JournalEvent::FundsWithdrawn { amount: withdrawn, .. },
+            JournalEvent::AccountOpened { client: 9, .. },
+            JournalEvent::FeeCharged { tx: 2, amount: fee, house: 9, .. },
+        ] if *withdrawn == dec!(2) && *fee == dec!(0.5)
+    ));
+    assert!(matches!(
+        events.last(),
+        Some(JournalEvent::FeeCharged { tx: 1, amount, .. }) if *amount == dec!(1)
+    ));
+
+    let replayed = &TxProc::replay(events).expect("failed to replay");
+    assert_eq!(summaries(proc), summaries(replayed));
+    assert_eq!(
+        summaries(replayed),
+        vec!["1,-3.5,0,-3.5,true", "9,1.5,0,1.5,false"]
+    );
+    assert_eq!(
+        replayed.account_summary(1).map(AccountSummary::fees),
+        Some(dec!(1.5))
+    );
+}
+
+#[test]
+fn inconsistent_journal() {
+    // balances before the event do not match
+    let result = TxProc::replay([
+        JournalEvent::AccountOpened {
+            client: 1,
+            currency: Currency::default(),
+        },
+        JournalEvent::FundsWithdrawn {
+            client: 1,
+            currency: Currency::default(),
+            tx: 1,
+            amount: dec!(1),
+            before: Balances {
+                available: dec!(1),
+                held: dec!(0),
+            },
+            after: Balances::default(),
+        },
+    ]);
+    assert!(matches!(result, Err(JournalError::Inconsistent(_))));
+
+    // account is not opened
+    let result = TxProc::replay([JournalEvent::AccountLocked {
+        client: 1,
+        currency: Currency::default(),
+    }]);
+    assert!(matches!(result, Err(JournalError::Inconsistent(_))));
+
+    // disputed transaction is not recorded
+    let result = TxProc::replay([
+        JournalEvent::AccountOpened {
+            client: 1,
+            currency: Currency::default(),
+        },
+        JournalEvent::FundsHeld {
+            client: 1,
+            currency: Currency::default(),
+            tx: 1,
+            shortfall: dec!(0),
+            policy: None,
+            before: Balances::default(),
+            after: Balances::default(),
+        },
+    ]);
+    assert!(matches!(result, Err(JournalError::Inconsistent(_))));
+
+    // undecodable line
+    let mut reader = JournalReader::new("{\"event\":\"account_opened\"}\n".as_bytes());
+    assert!(matches!(
+        reader.next(),
+        Some(Err(JournalError::Invalid { line: 1, .. }))
+    ));
+}
@@ -0,0 +1,110 @@
+use rust_decimal_macros::dec;
+use tx_proc::input::InvalidRecord;
+use tx_proc::json::{AccountSummaryJsonWriter, JsonLineErrorKind, TxRecordJsonReader};
+use tx_proc::*;
+
+#[test]
+fn read_records() {
+    let input = "{\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": \"1.5\"}\n\
+                 \n\
+                 {\"type\": \"withdrawal\", \"client\": 1, \"tx\": 2, \"amount\": 0.5}\n\
+                 {\"type\": \"invalid_type\", \"client\": 1, \"tx\": 3, \"amount\": \"1.0\"}\n\
+                 {\"type\": \"deposit\", \"client\": 1, \"tx\": 4}\n\
+                 {\"type\": \"deposit\", \"client\": -1, \"tx\": 5, \"amount\": \"1.0\"}\n\
+                 {\"type\": \"dispute\", \"client\": 1, \"tx\": 1, \"amount\": \"222\"}\n\
+                 {\"type\": \"resolve\", \"client\": 1, \"tx\": 1}\n";
+
+    let mut reader = TxRecordJsonReader::new(input.as_bytes());
+
+    match reader.next() {
+        Some(Ok(TxRecord::DEPOSIT(metadata, amount))) => {
+            assert_eq!(metadata.client_id(), 1);
+            assert_eq!(metadata.tx_id(), 1);
+            assert_eq!(amount, dec!(1.5));
+        }
+        _ => panic!("expected a deposit"),
+    }
+
+    // blank lines are skipped, amounts can be JSON numbers
+    match reader.next() {
+        Some(Ok(TxRecord::WITHDRAWAL(_, amount))) => assert_eq!(amount, dec!(0.5)),
+        _ => panic!("expected a withdrawal"),
+    }
+    assert_eq!(reader.line(), Some(3));
+
+    // unknown type, the line number is reported
+    let error = reader
+        .next()
+        .expect("expected a line")
+        .expect_err("expected an error");
+    assert!(matches!(
+        error.kind(),
+        JsonLineErrorKind::Invalid(InvalidRecord::UnknownType(t)) if t == "invalid_type"
+    ));
+    assert_eq!(error.line(), Some(4));
+    assert_eq!(error.code(), "unknown_type");
+
+    // missing amount on a deposit
+    let error = reader
+        .next()
+        .expect("expected a line")
+        .expect_err("expected an error");
+    assert_eq!(error.code(), "missing_amount");
+    assert_eq!(
+        reader.raw_record(),
+        "{\"type\": \"deposit\", \"client\": 1, \"tx\": 4}"
+    );
+
+    // negative client id
+    let error = reader
+        .next()
+        .expect("expected a line")
+        .expect_err("expected an error");
+    assert!(matches!(error.kind(), JsonLineErrorKind::Json(_)));
+    assert_eq!(error.code(), "malformed_line");
+
+    // the extraneous amount is discarded
+    assert!(matches!(reader.next(), Some(Ok(TxRecord::DISPUTE(_)))));
+    assert!(matches!(reader.next(), Some(Ok(TxRecord::RESOLVE(_)))));
+    assert!(reader.next().is_none());
+}
+
+#[test]
+fn write_summaries() {
+    let proc = &mut TxProc::default();
+
+    proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(1.5)))
+        .expect("valid deposit");
+
+    let expected_object =
+        "{\"client\":1,\"available\":\"1.5\",\"held\":\"0\",\"total\":\"1.5\",\"locked\":false}";
+
+    let mut output = Vec::new();
+    let mut writer = AccountSummaryJsonWriter::array(&mut output);
+    for (client_id, summary) in proc.summary_iterator() {
+        writer.write(*client_id, summary).expect("valid summary");
+    }
+    writer.finish().expect("flush to memory");
+    assert_eq!(
+        String::from_utf8(output).expect("valid UTF-8"),
+        format!("[{}]\n", expected_object)
+    );
+
+    let mut output = Vec::new();
+    let mut writer = AccountSummaryJsonWriter::lines(&mut output);
+    for (client_id, summary) in proc.summary_iterator() {
+        writer.write(*client_id, summary).expect("valid summary");
+    }
+    writer.finish().expect("flush to memory");
+    assert_eq!(
+        String::from_utf8(output).expect("valid UTF-8"),
+        format!("{}\n", expected_object)
+    );
+
+    // no account
+    let mut output = Vec::new();
+    AccountSummaryJsonWriter::array(&mut output)
+        .finish()
+        .expect("flush to memory");
+    assert_eq!(String::from_utf8(output).expect("valid UTF-8"), "[]\n");
+}
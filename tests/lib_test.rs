@@ -1,10 +1,12 @@
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
+use std::io;
+use tx_proc::store::{InMemoryStore, TransactionStore};
 use tx_proc::*;
 
 // todo try using a single struct as argument to make call sites more readable?
-fn assert_account_data(
-    proc: &TxProc,
+fn assert_account_data<S: TransactionStore>(
+    proc: &TxProc<S>,
     client_id: u16,
     available: Decimal,
     held: Decimal,
@@ -13,7 +15,7 @@ fn assert_account_data(
 ) {
     let summary = proc
         .account_summary(client_id)
-        .expect(&format!("summary for client {client_id} not found"));
+        .unwrap_or_else(|| panic!("summary for client {client_id} not found"));
     assert_eq!(summary.available_amount(), available);
     assert_eq!(summary.held_amount(), held);
     assert_eq!(summary.total_amount(), total);
@@ -29,28 +31,37 @@ fn repeated_transaction() {
     let proc = &mut TxProc::default();
 
     // deposit
-    proc.submit_tx_record(TxRecord::DEPOSIT(
-        TxRecordMetadata::new(CLIENT_1, DEPOSIT_1),
-        dec!(3),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, DEPOSIT_1),
+            dec!(3),
+        )),
+        Ok(Applied::Deposited)
+    );
 
     // available & total are increased
     assert_account_data(proc, CLIENT_1, dec!(3), dec!(0), dec!(3), false);
 
     // repeated transaction on same client
-    proc.submit_tx_record(TxRecord::DEPOSIT(
-        TxRecordMetadata::new(CLIENT_1, DEPOSIT_1),
-        dec!(3),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, DEPOSIT_1),
+            dec!(3),
+        )),
+        Err(TxRejection::DuplicateTransaction)
+    );
 
     // amounts do not change
     assert_account_data(proc, CLIENT_1, dec!(3), dec!(0), dec!(3), false);
 
     // repeated transaction on different client
-    proc.submit_tx_record(TxRecord::DEPOSIT(
-        TxRecordMetadata::new(CLIENT_2, DEPOSIT_1),
-        dec!(3),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_2, DEPOSIT_1),
+            dec!(3),
+        )),
+        Err(TxRejection::DuplicateTransaction)
+    );
 
     // no amount deposited for client 2
     assert_account_data(proc, CLIENT_2, dec!(0), dec!(0), dec!(0), false);
@@ -59,6 +70,18 @@ fn repeated_transaction() {
     assert_account_data(proc, CLIENT_1, dec!(3), dec!(0), dec!(3), false);
 
     assert_eq!(proc.summary_iterator().count(), 2);
+
+    // listed by client id, whatever the submission order
+    proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(0, 4), dec!(1)))
+        .expect("valid deposit");
+    let clients: Vec<u16> = proc.summary_iterator().map(|(client, _)| *client).collect();
+    assert_eq!(clients, [0, CLIENT_1, CLIENT_2]);
+    let clients: Vec<u16> = proc
+        .summary_iterator()
+        .rev()
+        .map(|(client, _)| *client)
+        .collect();
+    assert_eq!(clients, [CLIENT_2, CLIENT_1, 0]);
 }
 
 #[test]
@@ -69,19 +92,25 @@ fn multiple_clients() {
     let proc = &mut TxProc::default();
 
     // deposit on client 1
-    proc.submit_tx_record(TxRecord::DEPOSIT(
-        TxRecordMetadata::new(CLIENT_1, 2),
-        dec!(3),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, 2),
+            dec!(3),
+        )),
+        Ok(Applied::Deposited)
+    );
 
     // available & total are increased for client 1
     assert_account_data(proc, CLIENT_1, dec!(3), dec!(0), dec!(3), false);
 
     // deposit on client 2
-    proc.submit_tx_record(TxRecord::DEPOSIT(
-        TxRecordMetadata::new(CLIENT_2, 3),
-        dec!(4),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_2, 3),
+            dec!(4),
+        )),
+        Ok(Applied::Deposited)
+    );
 
     // available & total are increased for client 2
     assert_account_data(proc, CLIENT_2, dec!(4), dec!(0), dec!(4), false);
@@ -90,6 +119,18 @@ fn multiple_clients() {
     assert_account_data(proc, CLIENT_1, dec!(3), dec!(0), dec!(3), false);
 
     assert_eq!(proc.summary_iterator().count(), 2);
+
+    // listed by client id, whatever the submission order
+    proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(0, 4), dec!(1)))
+        .expect("valid deposit");
+    let clients: Vec<u16> = proc.summary_iterator().map(|(client, _)| *client).collect();
+    assert_eq!(clients, [0, CLIENT_1, CLIENT_2]);
+    let clients: Vec<u16> = proc
+        .summary_iterator()
+        .rev()
+        .map(|(client, _)| *client)
+        .collect();
+    assert_eq!(clients, [CLIENT_2, CLIENT_1, 0]);
 }
 
 #[test]
@@ -99,19 +140,25 @@ fn deposit() {
     let proc = &mut TxProc::default();
 
     // negative deposit amount
-    proc.submit_tx_record(TxRecord::DEPOSIT(
-        TxRecordMetadata::new(CLIENT_1, 2),
-        dec!(-3),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, 2),
+            dec!(-3),
+        )),
+        Err(TxRejection::NegativeAmount)
+    );
 
     // amounts do not change
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
 
     // valid deposit
-    proc.submit_tx_record(TxRecord::DEPOSIT(
-        TxRecordMetadata::new(CLIENT_1, 2),
-        dec!(3),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, 2),
+            dec!(3),
+        )),
+        Ok(Applied::Deposited)
+    );
 
     // available & total are increased
     assert_account_data(proc, CLIENT_1, dec!(3), dec!(0), dec!(3), false);
@@ -124,37 +171,49 @@ fn withdrawals() {
     let proc = &mut TxProc::default();
 
     // deposit
-    proc.submit_tx_record(TxRecord::DEPOSIT(
-        TxRecordMetadata::new(CLIENT_1, 2),
-        dec!(3),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, 2),
+            dec!(3),
+        )),
+        Ok(Applied::Deposited)
+    );
 
     // available & total are increased
     assert_account_data(proc, CLIENT_1, dec!(3), dec!(0), dec!(3), false);
 
     // withdrawal with insufficient available amount
-    proc.submit_tx_record(TxRecord::WITHDRAWAL(
-        TxRecordMetadata::new(CLIENT_1, 3),
-        dec!(4),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(
+            TxRecordMetadata::new(CLIENT_1, 3),
+            dec!(4),
+        )),
+        Err(TxRejection::InsufficientFunds)
+    );
 
     // amounts do not change
     assert_account_data(proc, CLIENT_1, dec!(3), dec!(0), dec!(3), false);
 
     // invalid negative withdrawal
-    proc.submit_tx_record(TxRecord::WITHDRAWAL(
-        TxRecordMetadata::new(CLIENT_1, 4),
-        dec!(-1),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(
+            TxRecordMetadata::new(CLIENT_1, 4),
+            dec!(-1),
+        )),
+        Err(TxRejection::NegativeAmount)
+    );
 
     // amounts do not change
     assert_account_data(proc, CLIENT_1, dec!(3), dec!(0), dec!(3), false);
 
     // valid withdrawal
-    proc.submit_tx_record(TxRecord::WITHDRAWAL(
-        TxRecordMetadata::new(CLIENT_1, 4),
-        dec!(3),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(
+            TxRecordMetadata::new(CLIENT_1, 4),
+            dec!(3),
+        )),
+        Ok(Applied::Withdrawn)
+    );
 
     // available & total are decreased
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
@@ -174,54 +233,72 @@ fn dispute() {
     let proc = &mut TxProc::default();
 
     // dispute a non-existent transaction
-    proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
-        CLIENT_1,
-        NON_EXISTENT_TX,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+            CLIENT_1,
+            NON_EXISTENT_TX,
+        ))),
+        Err(TxRejection::UnknownTransaction)
+    );
 
     // no change in amounts
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
 
     // deposit
-    proc.submit_tx_record(TxRecord::DEPOSIT(
-        TxRecordMetadata::new(CLIENT_1, DEPOSIT_1),
-        dec!(2),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, DEPOSIT_1),
+            dec!(2),
+        )),
+        Ok(Applied::Deposited)
+    );
 
     // available & total are increased
     assert_account_data(proc, CLIENT_1, dec!(2), dec!(0), dec!(2), false);
 
     // withdrawal
-    proc.submit_tx_record(TxRecord::WITHDRAWAL(
-        TxRecordMetadata::new(CLIENT_1, WITHDRAWAL_1),
-        dec!(2),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(
+            TxRecordMetadata::new(CLIENT_1, WITHDRAWAL_1),
+            dec!(2),
+        )),
+        Ok(Applied::Withdrawn)
+    );
 
     // available & total are decreased
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
 
     // dispute the withdrawal
-    proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
-        CLIENT_1,
-        WITHDRAWAL_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+            CLIENT_1,
+            WITHDRAWAL_1,
+        ))),
+        Err(TxRejection::NotDisputable)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
 
     // dispute the deposit but with wrong client 2
-    proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
-        CLIENT_2, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+            CLIENT_2, DEPOSIT_1,
+        ))),
+        Err(TxRejection::ClientMismatch)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
     assert_account_data(proc, CLIENT_2, dec!(0), dec!(0), dec!(0), false);
 
     // dispute the deposit
-    proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
-        CLIENT_1, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Ok(Applied::Disputed)
+    );
 
     // available is decreased by the deposit amount
     // held is increased by the deposit amount
@@ -229,9 +306,12 @@ fn dispute() {
     assert_account_data(proc, CLIENT_1, dec!(-2), dec!(2), dec!(0), false);
 
     // duplicated dispute
-    proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
-        CLIENT_1, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Err(TxRejection::AlreadyDisputed)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(-2), dec!(2), dec!(0), false);
@@ -247,50 +327,68 @@ fn resolve() {
     let proc = &mut TxProc::default();
 
     // resolve a non-existent transaction
-    proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(CLIENT_1, 2)));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(CLIENT_1, 2))),
+        Err(TxRejection::UnknownTransaction)
+    );
 
     // no change in amounts
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
 
     // deposit
-    proc.submit_tx_record(TxRecord::DEPOSIT(
-        TxRecordMetadata::new(CLIENT_1, DEPOSIT_1),
-        dec!(2),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, DEPOSIT_1),
+            dec!(2),
+        )),
+        Ok(Applied::Deposited)
+    );
 
     // available & total are increased
     assert_account_data(proc, CLIENT_1, dec!(2), dec!(0), dec!(2), false);
 
     // withdrawal
-    proc.submit_tx_record(TxRecord::WITHDRAWAL(
-        TxRecordMetadata::new(CLIENT_1, WITHDRAWAL_1),
-        dec!(2),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(
+            TxRecordMetadata::new(CLIENT_1, WITHDRAWAL_1),
+            dec!(2),
+        )),
+        Ok(Applied::Withdrawn)
+    );
 
     // available & total are decreased
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
 
     // resolve the withdrawal
-    proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
-        CLIENT_1,
-        WITHDRAWAL_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
+            CLIENT_1,
+            WITHDRAWAL_1,
+        ))),
+        Err(TxRejection::NotDisputable)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
 
     // resolve the non-disputed deposit
-    proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
-        CLIENT_1, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Err(TxRejection::NotDisputed)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
 
     // dispute the deposit
-    proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
-        CLIENT_1, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Ok(Applied::Disputed)
+    );
 
     // available is decreased by the deposit amount
     // held is increased by the deposit amount
@@ -298,18 +396,24 @@ fn resolve() {
     assert_account_data(proc, CLIENT_1, dec!(-2), dec!(2), dec!(0), false);
 
     // resolve the deposit but with wrong client 2
-    proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
-        CLIENT_2, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
+            CLIENT_2, DEPOSIT_1,
+        ))),
+        Err(TxRejection::ClientMismatch)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(-2), dec!(2), dec!(0), false);
     assert_account_data(proc, CLIENT_2, dec!(0), dec!(0), dec!(0), false);
 
     // resolve the disputed deposit
-    proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
-        CLIENT_1, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Ok(Applied::Resolved)
+    );
 
     // available is increased by the deposit amount
     // held is decreased by the deposit amount
@@ -317,17 +421,23 @@ fn resolve() {
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
 
     // duplicated resolve
-    proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
-        CLIENT_1, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Err(TxRejection::NotDisputed)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
 
     // dispute a second time the deposit
-    proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
-        CLIENT_1, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Ok(Applied::Disputed)
+    );
 
     // available is decreased by the deposit amount
     // held is increased by the deposit amount
@@ -335,9 +445,12 @@ fn resolve() {
     assert_account_data(proc, CLIENT_1, dec!(-2), dec!(2), dec!(0), false);
 
     // resolve a second time the disputed deposit
-    proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
-        CLIENT_1, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Ok(Applied::Resolved)
+    );
 
     // available is increased by the deposit amount
     // held is decreased by the deposit amount
@@ -357,50 +470,68 @@ fn chargeback() {
     let proc = &mut TxProc::default();
 
     // chargeback a non-existent transaction
-    proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(CLIENT_1, 2)));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(CLIENT_1, 2))),
+        Err(TxRejection::UnknownTransaction)
+    );
 
     // no change in amounts
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
 
     // deposit
-    proc.submit_tx_record(TxRecord::DEPOSIT(
-        TxRecordMetadata::new(CLIENT_1, DEPOSIT_1),
-        dec!(2),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, DEPOSIT_1),
+            dec!(2),
+        )),
+        Ok(Applied::Deposited)
+    );
 
     // available & total are increased
     assert_account_data(proc, CLIENT_1, dec!(2), dec!(0), dec!(2), false);
 
     // withdrawal
-    proc.submit_tx_record(TxRecord::WITHDRAWAL(
-        TxRecordMetadata::new(CLIENT_1, WITHDRAWAL_1),
-        dec!(2),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(
+            TxRecordMetadata::new(CLIENT_1, WITHDRAWAL_1),
+            dec!(2),
+        )),
+        Ok(Applied::Withdrawn)
+    );
 
     // available & total are decreased
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
 
     // chargeback the withdrawal
-    proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(
-        CLIENT_1,
-        WITHDRAWAL_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(
+            CLIENT_1,
+            WITHDRAWAL_1,
+        ))),
+        Err(TxRejection::NotDisputable)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
 
     // chargeback the non-disputed deposit
-    proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(
-        CLIENT_1, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Err(TxRejection::NotDisputed)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
 
     // dispute the deposit
-    proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
-        CLIENT_1, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Ok(Applied::Disputed)
+    );
 
     // available is decreased by the deposit amount
     // held is increased by the deposit amount
@@ -408,18 +539,24 @@ fn chargeback() {
     assert_account_data(proc, CLIENT_1, dec!(-2), dec!(2), dec!(0), false);
 
     // chargeback the disputed deposit but on wrong client 2
-    proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(
-        CLIENT_2, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(
+            CLIENT_2, DEPOSIT_1,
+        ))),
+        Err(TxRejection::ClientMismatch)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(-2), dec!(2), dec!(0), false);
     assert_account_data(proc, CLIENT_2, dec!(0), dec!(0), dec!(0), false);
 
     // chargeback the disputed deposit
-    proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(
-        CLIENT_1, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Ok(Applied::ChargedBack)
+    );
 
     // available does not change
     // held is decreased by the deposit amount
@@ -428,44 +565,1058 @@ fn chargeback() {
     assert_account_data(proc, CLIENT_1, dec!(-2), dec!(0), dec!(-2), true);
 
     // duplicated chargeback
-    proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(
-        CLIENT_1, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Err(TxRejection::AccountLocked)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(-2), dec!(0), dec!(-2), true);
 
     // try resolve the chargeback deposit
-    proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
-        CLIENT_1, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Err(TxRejection::AccountLocked)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(-2), dec!(0), dec!(-2), true);
 
     // try dispute again on chargeback deposit
-    proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
-        CLIENT_1, DEPOSIT_1,
-    )));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Err(TxRejection::AccountLocked)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(-2), dec!(0), dec!(-2), true);
 
     // deposit on locked account
-    proc.submit_tx_record(TxRecord::DEPOSIT(
-        TxRecordMetadata::new(CLIENT_1, DEPOSIT_2),
-        dec!(2),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, DEPOSIT_2),
+            dec!(2),
+        )),
+        Err(TxRejection::AccountLocked)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(-2), dec!(0), dec!(-2), true);
 
     // withdrawal on locked account
-    proc.submit_tx_record(TxRecord::WITHDRAWAL(
-        TxRecordMetadata::new(CLIENT_1, WITHDRAWAL_2),
-        dec!(2),
-    ));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(
+            TxRecordMetadata::new(CLIENT_1, WITHDRAWAL_2),
+            dec!(2),
+        )),
+        Err(TxRejection::AccountLocked)
+    );
 
     // nothing happens
     assert_account_data(proc, CLIENT_1, dec!(-2), dec!(0), dec!(-2), true);
 }
+
+#[test]
+fn declined_withdrawal() {
+    const CLIENT_1: u16 = 1;
+    const DEPOSIT_1: u32 = 3;
+    const WITHDRAWAL_1: u32 = 4;
+
+    let proc = &mut TxProc::with_config(TxProcConfig {
+        withdrawal_dispute_policy: WithdrawalDisputePolicy::HoldReversal,
+        ..TxProcConfig::default()
+    });
+
+    // withdrawal with insufficient available amount
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(
+            TxRecordMetadata::new(CLIENT_1, WITHDRAWAL_1),
+            dec!(2),
+        )),
+        Err(TxRejection::InsufficientFunds)
+    );
+
+    // the declined withdrawal can not be disputed
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+            CLIENT_1,
+            WITHDRAWAL_1,
+        ))),
+        Err(TxRejection::NotDisputable)
+    );
+
+    // nothing happens
+    assert_account_data(proc, CLIENT_1, dec!(0), dec!(0), dec!(0), false);
+
+    // deposit
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, DEPOSIT_1),
+            dec!(2),
+        )),
+        Ok(Applied::Deposited)
+    );
+
+    // the transaction id of the declined withdrawal can not be used again
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(
+            TxRecordMetadata::new(CLIENT_1, WITHDRAWAL_1),
+            dec!(2),
+        )),
+        Err(TxRejection::DuplicateTransaction)
+    );
+
+    // nor disputed once the funds are there
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+            CLIENT_1,
+            WITHDRAWAL_1,
+        ))),
+        Err(TxRejection::NotDisputable)
+    );
+
+    assert_account_data(proc, CLIENT_1, dec!(2), dec!(0), dec!(2), false);
+}
+
+// deposits 3 then withdraws 2 on client 1
+#[test]
+fn strict() {
+    const CLIENT_1: u16 = 1;
+    const CLIENT_2: u16 = 2;
+
+    let proc = &mut TxProc::with_config(TxProcConfig {
+        strict: true,
+        journal: true,
+        ..TxProcConfig::default()
+    });
+
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, 1),
+            dec!(2),
+        )),
+        Ok(Applied::Deposited)
+    );
+    assert_eq!(proc.drain_journal().count(), 2);
+
+    // the first rejection halts the processing
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(
+            TxRecordMetadata::new(CLIENT_2, 2),
+            dec!(1),
+        )),
+        Err(TxRejection::InsufficientFunds)
+    );
+    assert!(proc.is_halted());
+
+    // the rejected record leaves no trace, not even an opened account
+    assert!(proc.account_summary(CLIENT_2).is_none());
+    assert_eq!(proc.drain_journal().count(), 0);
+
+    // every later record is rejected
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, 3),
+            dec!(2),
+        )),
+        Err(TxRejection::Halted)
+    );
+
+    assert_account_data(proc, CLIENT_1, dec!(2), dec!(0), dec!(2), false);
+}
+
+fn withdrawal_dispute_setup(policy: WithdrawalDisputePolicy) -> TxProc {
+    let mut proc = TxProc::with_config(TxProcConfig {
+        withdrawal_dispute_policy: policy,
+        ..TxProcConfig::default()
+    });
+
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 3), dec!(3))),
+        Ok(Applied::Deposited)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 4), dec!(2))),
+        Ok(Applied::Withdrawn)
+    );
+
+    proc
+}
+
+#[test]
+fn dispute_withdrawal() {
+    const CLIENT_1: u16 = 1;
+    const CLIENT_2: u16 = 2;
+    const WITHDRAWAL_1: u32 = 4;
+
+    // default policy
+    let proc = &mut withdrawal_dispute_setup(WithdrawalDisputePolicy::Reject);
+
+    // dispute the withdrawal
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+            CLIENT_1,
+            WITHDRAWAL_1,
+        ))),
+        Err(TxRejection::NotDisputable)
+    );
+
+    // nothing happens
+    assert_account_data(proc, CLIENT_1, dec!(1), dec!(0), dec!(1), false);
+
+    for policy in [
+        WithdrawalDisputePolicy::HoldReversal,
+        WithdrawalDisputePolicy::ProvisionalCredit,
+    ] {
+        let proc = &mut withdrawal_dispute_setup(policy);
+
+        // dispute the withdrawal but with wrong client 2
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+                CLIENT_2,
+                WITHDRAWAL_1,
+            ))),
+            Err(TxRejection::ClientMismatch)
+        );
+
+        // nothing happens
+        assert_account_data(proc, CLIENT_1, dec!(1), dec!(0), dec!(1), false);
+        assert_account_data(proc, CLIENT_2, dec!(0), dec!(0), dec!(0), false);
+
+        // dispute the withdrawal
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+                CLIENT_1,
+                WITHDRAWAL_1,
+            ))),
+            Ok(Applied::Disputed)
+        );
+
+        match policy {
+            // held & total are increased by the withdrawal amount
+            WithdrawalDisputePolicy::HoldReversal => {
+                assert_account_data(proc, CLIENT_1, dec!(1), dec!(2), dec!(3), false)
+            }
+            // available & total are increased by the withdrawal amount
+            _ => assert_account_data(proc, CLIENT_1, dec!(3), dec!(0), dec!(3), false),
+        }
+
+        // duplicated dispute
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+                CLIENT_1,
+                WITHDRAWAL_1,
+            ))),
+            Err(TxRejection::AlreadyDisputed)
+        );
+    }
+}
+
+#[test]
+fn resolve_withdrawal() {
+    const CLIENT_1: u16 = 1;
+    const WITHDRAWAL_1: u32 = 4;
+
+    for policy in [
+        WithdrawalDisputePolicy::HoldReversal,
+        WithdrawalDisputePolicy::ProvisionalCredit,
+    ] {
+        let proc = &mut withdrawal_dispute_setup(policy);
+
+        // resolve the non-disputed withdrawal
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
+                CLIENT_1,
+                WITHDRAWAL_1,
+            ))),
+            Err(TxRejection::NotDisputed)
+        );
+
+        // dispute the withdrawal
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+                CLIENT_1,
+                WITHDRAWAL_1,
+            ))),
+            Ok(Applied::Disputed)
+        );
+
+        // resolve the disputed withdrawal
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(
+                CLIENT_1,
+                WITHDRAWAL_1,
+            ))),
+            Ok(Applied::Resolved)
+        );
+
+        // the withdrawal stands, amounts are back to their value before the dispute
+        assert_account_data(proc, CLIENT_1, dec!(1), dec!(0), dec!(1), false);
+    }
+}
+
+#[test]
+fn chargeback_withdrawal() {
+    const CLIENT_1: u16 = 1;
+    const WITHDRAWAL_1: u32 = 4;
+
+    for policy in [
+        WithdrawalDisputePolicy::HoldReversal,
+        WithdrawalDisputePolicy::ProvisionalCredit,
+    ] {
+        let proc = &mut withdrawal_dispute_setup(policy);
+
+        // chargeback the non-disputed withdrawal
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(
+                CLIENT_1,
+                WITHDRAWAL_1,
+            ))),
+            Err(TxRejection::NotDisputed)
+        );
+
+        // dispute the withdrawal
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+                CLIENT_1,
+                WITHDRAWAL_1,
+            ))),
+            Ok(Applied::Disputed)
+        );
+
+        // chargeback the disputed withdrawal
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(
+                CLIENT_1,
+                WITHDRAWAL_1,
+            ))),
+            Ok(Applied::ChargedBack)
+        );
+
+        // the withdrawal is reversed into available
+        // account is locked
+        assert_account_data(proc, CLIENT_1, dec!(3), dec!(0), dec!(3), true);
+
+        // duplicated chargeback
+        assert_eq!(
+            proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(
+                CLIENT_1,
+                WITHDRAWAL_1,
+            ))),
+            Err(TxRejection::AccountLocked)
+        );
+    }
+}
+
+// in-memory store refusing to record more than one transaction
+#[derive(Default)]
+struct FullStore {
+    store: InMemoryStore,
+    len: usize,
+}
+
+impl TransactionStore for FullStore {
+    fn get(&self, tx_id: u32) -> io::Result<Option<(TxRecord, TxRecordFlags)>> {
+        self.store.get(tx_id)
+    }
+
+    fn insert_if_absent(&mut self, record: TxRecord) -> io::Result<bool> {
+        if self.len == 1 {
+            return Err(io::Error::new(io::ErrorKind::StorageFull, "store is full"));
+        }
+        let inserted = self.store.insert_if_absent(record)?;
+        self.len += inserted as usize;
+        Ok(inserted)
+    }
+
+    fn update_flags(&mut self, tx_id: u32, flags: TxRecordFlags) -> io::Result<()> {
+        self.store.update_flags(tx_id, flags)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = io::Result<(TxRecord, TxRecordFlags)>> + '_> {
+        self.store.iter()
+    }
+}
+
+fn deposit_dispute_setup(policy: DepositDisputePolicy) -> TxProc {
+    let mut proc = TxProc::with_config(TxProcConfig {
+        deposit_dispute_policy: policy,
+        ..TxProcConfig::default()
+    });
+
+    // the disputed deposit has been partly withdrawn
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(5))),
+        Ok(Applied::Deposited)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 2), dec!(3))),
+        Ok(Applied::Withdrawn)
+    );
+
+    proc
+}
+
+#[test]
+fn dispute_spent_deposit() {
+    const CLIENT_1: u16 = 1;
+
+    let proc = &mut deposit_dispute_setup(DepositDisputePolicy::AllowNegative);
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(CLIENT_1, 1))),
+        Ok(Applied::Disputed)
+    );
+    assert_account_data(proc, CLIENT_1, dec!(-3), dec!(5), dec!(2), false);
+    assert_eq!(
+        proc.account_summary(CLIENT_1).map(|s| s.is_negative()),
+        Some(true)
+    );
+
+    let proc = &mut deposit_dispute_setup(DepositDisputePolicy::Reject);
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(CLIENT_1, 1))),
+        Err(TxRejection::DisputeExceedsAvailable)
+    );
+    assert_account_data(proc, CLIENT_1, dec!(2), dec!(0), dec!(2), false);
+    // the transaction is not disputed
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(CLIENT_1, 1))),
+        Err(TxRejection::NotDisputed)
+    );
+
+    let proc = &mut deposit_dispute_setup(DepositDisputePolicy::HoldAvailable);
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(CLIENT_1, 1))),
+        Ok(Applied::Disputed)
+    );
+    assert_account_data(proc, CLIENT_1, dec!(0), dec!(2), dec!(2), false);
+    let summary = proc.account_summary(CLIENT_1).expect("summary");
+    assert_eq!(summary.shortfall(), dec!(3));
+    assert!(summary.is_negative());
+
+    // the resolve only releases the held amount
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(CLIENT_1, 1))),
+        Ok(Applied::Resolved)
+    );
+    assert_account_data(proc, CLIENT_1, dec!(2), dec!(0), dec!(2), false);
+    let summary = proc.account_summary(CLIENT_1).expect("summary");
+    assert_eq!(summary.shortfall(), dec!(0));
+    assert!(!summary.is_negative());
+
+    // the chargeback debits the shortfall from the available amount
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(CLIENT_1, 1))),
+        Ok(Applied::Disputed)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(CLIENT_1, 1))),
+        Ok(Applied::ChargedBack)
+    );
+    assert_account_data(proc, CLIENT_1, dec!(-3), dec!(0), dec!(-3), true);
+    assert_eq!(
+        proc.account_summary(CLIENT_1).map(|s| s.shortfall()),
+        Some(dec!(0))
+    );
+}
+
+#[test]
+fn custom_transaction_store() {
+    const CLIENT_1: u16 = 1;
+    const DEPOSIT_1: u32 = 3;
+    const DEPOSIT_2: u32 = 4;
+
+    let proc = &mut TxProc::with_store(TxProcConfig::default(), FullStore::default());
+
+    // deposit
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, DEPOSIT_1),
+            dec!(2),
+        )),
+        Ok(Applied::Deposited)
+    );
+
+    // dispute the deposit, flags are updated in the custom store
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(
+            CLIENT_1, DEPOSIT_1,
+        ))),
+        Ok(Applied::Disputed)
+    );
+
+    assert_account_data(proc, CLIENT_1, dec!(0), dec!(2), dec!(2), false);
+
+    // the store fails to record the deposit
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(
+            TxRecordMetadata::new(CLIENT_1, DEPOSIT_2),
+            dec!(2),
+        )),
+        Err(TxRejection::Storage(io::ErrorKind::StorageFull))
+    );
+
+    // nothing happens
+    assert_account_data(proc, CLIENT_1, dec!(0), dec!(2), dec!(2), false);
+}
+
+#[test]
+fn multiple_currencies() {
+    let eur = Currency::new("eur").expect("valid currency");
+    let usd = Currency::new("USD").expect("valid currency");
+    let in_currency =
+        |client, tx, currency| TxRecordMetadata::new(client, tx).with_currency(currency);
+
+    let proc = &mut TxProc::default();
+
+    for record in [
+        TxRecord::DEPOSIT(in_currency(1, 1, eur), dec!(10)),
+        TxRecord::DEPOSIT(in_currency(1, 2, usd), dec!(3)),
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 3), dec!(5)),
+    ] {
+        assert_eq!(proc.submit_tx_record(record), Ok(Applied::Deposited));
+    }
+
+    // balances are kept per currency
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(in_currency(1, 4, usd), dec!(4))),
+        Err(TxRejection::InsufficientFunds)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(in_currency(1, 5, eur), dec!(4))),
+        Ok(Applied::Withdrawn)
+    );
+
+    // the dispute applies to the currency of the deposit, whatever its own currency
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(1, 2))),
+        Ok(Applied::Disputed)
+    );
+    let summary = proc.account_summary_in(1, usd).expect("usd account");
+    assert_eq!(summary.available_amount(), dec!(0));
+    assert_eq!(summary.held_amount(), dec!(3));
+
+    // the chargeback only locks the account of the currency
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CHARGEBACK(in_currency(1, 2, eur))),
+        Ok(Applied::ChargedBack)
+    );
+    assert!(proc
+        .account_summary_in(1, usd)
+        .expect("usd account")
+        .is_locked());
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(in_currency(1, 6, eur), dec!(1))),
+        Ok(Applied::Deposited)
+    );
+
+    assert_account_data(proc, 1, dec!(5), dec!(0), dec!(5), false);
+    assert_eq!(
+        proc.account_summary_in(1, eur)
+            .expect("eur account")
+            .available_amount(),
+        dec!(7)
+    );
+
+    // default currency first, then by code
+    let currencies: Vec<String> = proc
+        .summary_iterator()
+        .map(|(_, summary)| summary.currency().to_string())
+        .collect();
+    assert_eq!(currencies, ["", "EUR", "USD"]);
+    assert!(proc.has_currencies());
+
+    assert_eq!(Currency::new(""), Some(Currency::default()));
+    assert_eq!(Currency::new("EURO"), None);
+    assert_eq!(Currency::new("E1R"), None);
+}
+
+#[test]
+fn credit_limit() {
+    let eur = Currency::new("EUR").expect("valid currency");
+    let proc = &mut TxProc::default();
+
+    // set before the account is opened
+    proc.set_credit_limit(1, Currency::default(), dec!(10));
+
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(5))),
+        Ok(Applied::Deposited)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 2), dec!(12))),
+        Ok(Applied::Withdrawn)
+    );
+    assert_account_data(proc, 1, dec!(-7), dec!(0), dec!(-7), false);
+    assert_eq!(proc.account_summary(1).map(|s| s.headroom()), Some(dec!(3)));
+
+    // beyond the limit
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 3), dec!(4))),
+        Err(TxRejection::InsufficientFunds)
+    );
+
+    // the limit only applies to the account of its currency
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(
+            TxRecordMetadata::new(1, 4).with_currency(eur),
+            dec!(1)
+        )),
+        Err(TxRejection::InsufficientFunds)
+    );
+
+    // a dispute can take the account over its limit
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(1, 1))),
+        Ok(Applied::Disputed)
+    );
+    assert_eq!(
+        proc.account_summary(1).map(|s| s.headroom()),
+        Some(dec!(-2))
+    );
+
+    // updated on the opened account, negative limits are set to zero
+    proc.set_credit_limit(1, Currency::default(), dec!(-1));
+    assert_eq!(
+        proc.account_summary(1).map(|s| s.credit_limit()),
+        Some(dec!(0))
+    );
+    assert!(proc.has_credit_limits());
+}
+
+#[test]
+fn transfer() {
+    let eur = Currency::new("EUR").expect("valid currency");
+    let proc = &mut TxProc::default();
+
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(5))),
+        Ok(Applied::Deposited)
+    );
+    // the destination account is opened by the transfer
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::TRANSFER(TxRecordMetadata::new(1, 2), 2, dec!(3))),
+        Ok(Applied::Transferred)
+    );
+    assert_account_data(proc, 1, dec!(2), dec!(0), dec!(2), false);
+    assert_account_data(proc, 2, dec!(3), dec!(0), dec!(3), false);
+
+    for (record, rejection) in [
+        (
+            TxRecord::TRANSFER(TxRecordMetadata::new(1, 3), 2, dec!(-1)),
+            TxRejection::NegativeAmount,
+        ),
+        (
+            TxRecord::TRANSFER(TxRecordMetadata::new(1, 3), 1, dec!(1)),
+            TxRejection::SelfTransfer,
+        ),
+        (
+            TxRecord::TRANSFER(TxRecordMetadata::new(1, 2), 2, dec!(1)),
+            TxRejection::DuplicateTransaction,
+        ),
+        (
+            TxRecord::TRANSFER(TxRecordMetadata::new(1, 3), 2, dec!(3)),
+            TxRejection::InsufficientFunds,
+        ),
+        // the declined transfer keeps its transaction id, it can not be disputed
+        (
+            TxRecord::TRANSFER(TxRecordMetadata::new(1, 3), 2, dec!(1)),
+            TxRejection::DuplicateTransaction,
+        ),
+        (
+            TxRecord::DISPUTE(TxRecordMetadata::new(2, 3)),
+            TxRejection::NotDisputable,
+        ),
+        // between the accounts of the currency
+        (
+            TxRecord::TRANSFER(TxRecordMetadata::new(1, 4).with_currency(eur), 2, dec!(1)),
+            TxRejection::InsufficientFunds,
+        ),
+        // only the destination can dispute the transfer
+        (
+            TxRecord::DISPUTE(TxRecordMetadata::new(1, 2)),
+            TxRejection::ClientMismatch,
+        ),
+    ] {
+        assert_eq!(proc.submit_tx_record(record), Err(rejection));
+    }
+    assert_account_data(proc, 1, dec!(2), dec!(0), dec!(2), false);
+    assert_account_data(proc, 2, dec!(3), dec!(0), dec!(3), false);
+
+    // disputed like a deposit of the destination
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(2, 2))),
+        Ok(Applied::Disputed)
+    );
+    assert_account_data(proc, 2, dec!(0), dec!(3), dec!(3), false);
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(2, 2))),
+        Ok(Applied::Resolved)
+    );
+    assert_account_data(proc, 2, dec!(3), dec!(0), dec!(3), false);
+
+    // the chargeback returns the amount to the source and locks the destination
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(2, 2))),
+        Ok(Applied::Disputed)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(2, 2))),
+        Ok(Applied::ChargedBack)
+    );
+    assert_account_data(proc, 1, dec!(5), dec!(0), dec!(5), false);
+    assert_account_data(proc, 2, dec!(0), dec!(0), dec!(0), true);
+
+    // the locked flag applies to both sides
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::TRANSFER(TxRecordMetadata::new(1, 4), 2, dec!(1))),
+        Err(TxRejection::DestinationLocked)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::TRANSFER(TxRecordMetadata::new(2, 4), 1, dec!(0))),
+        Err(TxRejection::AccountLocked)
+    );
+    assert_account_data(proc, 1, dec!(5), dec!(0), dec!(5), false);
+}
+
+#[test]
+fn unlock() {
+    let proc = &mut TxProc::default();
+    let unlock = |tx| TxRecord::UNLOCK(TxRecordMetadata::new(1, tx), "ticket 42".to_string());
+
+    assert_eq!(
+        proc.submit_tx_record(unlock(1)),
+        Err(TxRejection::NotLocked)
+    );
+    // a rejected unlock does not open the account
+    assert!(proc.account_summary(1).is_none());
+
+    for record in [
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(3)),
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 2), dec!(2)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(1, 1)),
+        TxRecord::CHARGEBACK(TxRecordMetadata::new(1, 1)),
+    ] {
+        proc.submit_tx_record(record).expect("valid record");
+    }
+    assert_account_data(proc, 1, dec!(2), dec!(0), dec!(2), true);
+
+    assert_eq!(proc.submit_tx_record(unlock(3)), Ok(Applied::Unlocked));
+    assert_account_data(proc, 1, dec!(2), dec!(0), dec!(2), false);
+    assert_eq!(
+        proc.submit_tx_record(unlock(3)),
+        Err(TxRejection::NotLocked)
+    );
+
+    // the account is active again, the charged back transaction stays charged back
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 3), dec!(1))),
+        Ok(Applied::Withdrawn)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(1, 1))),
+        Err(TxRejection::AlreadyChargedBack)
+    );
+    assert!(unlock(4).is_admin());
+}
+
+#[test]
+fn adjustment() {
+    let proc = &mut TxProc::default();
+    let adjustment = |tx, bucket, amount| {
+        TxRecord::ADJUSTMENT(
+            TxRecordMetadata::new(1, tx),
+            bucket,
+            amount,
+            "incident 7".to_string(),
+        )
+    };
+
+    proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(5)))
+        .expect("valid record");
+    assert_eq!(
+        proc.submit_tx_record(adjustment(2, Bucket::Available, dec!(-1.5))),
+        Ok(Applied::Adjusted)
+    );
+    assert_eq!(
+        proc.submit_tx_record(adjustment(3, Bucket::Held, dec!(2))),
+        Ok(Applied::Adjusted)
+    );
+    assert_account_data(proc, 1, dec!(3.5), dec!(2), dec!(5.5), false);
+    assert!(proc.has_adjustments());
+
+    // the transaction id is recorded, the adjustment can not be disputed
+    assert_eq!(
+        proc.submit_tx_record(adjustment(2, Bucket::Available, dec!(1))),
+        Err(TxRejection::DuplicateTransaction)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 3), dec!(1))),
+        Err(TxRejection::DuplicateTransaction)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(1, 2))),
+        Err(TxRejection::NotDisputable)
+    );
+
+    // adjustments also apply to locked accounts
+    for record in [
+        TxRecord::DISPUTE(TxRecordMetadata::new(1, 1)),
+        TxRecord::CHARGEBACK(TxRecordMetadata::new(1, 1)),
+    ] {
+        proc.submit_tx_record(record).expect("valid record");
+    }
+    assert_account_data(proc, 1, dec!(-1.5), dec!(2), dec!(0.5), true);
+    assert_eq!(
+        proc.submit_tx_record(adjustment(4, Bucket::Available, dec!(1.5))),
+        Ok(Applied::Adjusted)
+    );
+    assert_account_data(proc, 1, dec!(0), dec!(2), dec!(2), true);
+
+    let summary = proc.account_summary(1).expect("account 1");
+    assert_eq!(summary.adjustments(), 3);
+    assert_eq!(summary.adjusted_amount(), dec!(2));
+    assert!(adjustment(5, Bucket::Held, dec!(1)).is_admin());
+}
+
+#[test]
+fn fees() {
+    let proc = &mut TxProc::with_config(TxProcConfig {
+        fees: FeeSchedule {
+            house_client: 9,
+            withdrawal_flat: dec!(0.1),
+            withdrawal_rate: dec!(0.01),
+            dispute: dec!(0.5),
+            chargeback: dec!(1),
+        },
+        ..TxProcConfig::default()
+    });
+    assert!(proc.has_fees());
+
+    for record in [
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(10)),
+        TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 2), dec!(5)),
+    ] {
+        proc.submit_tx_record(record).expect("valid record");
+    }
+    assert_account_data(proc, 1, dec!(4.85), dec!(0), dec!(4.85), false);
+    assert_account_data(proc, 9, dec!(0.15), dec!(0), dec!(0.15), false);
+
+    // the available amount covers the amount, not the fee
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 3), dec!(4.8))),
+        Err(TxRejection::InsufficientFunds)
+    );
+
+    for record in [
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 4), dec!(2)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(1, 4)),
+    ] {
+        proc.submit_tx_record(record).expect("valid record");
+    }
+    assert_account_data(proc, 1, dec!(4.35), dec!(2), dec!(6.35), false);
+
+    // a rejected record is free
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(1, 4))),
+        Err(TxRejection::AlreadyDisputed)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(1, 4))),
+        Ok(Applied::ChargedBack)
+    );
+    assert_account_data(proc, 1, dec!(3.35), dec!(0), dec!(3.35), true);
+    assert_eq!(
+        proc.account_summary(1).map(AccountSummary::fees),
+        Some(dec!(1.65))
+    );
+
+    // the records of the house account are free
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(
+            TxRecordMetadata::new(9, 5),
+            dec!(1.65)
+        )),
+        Ok(Applied::Withdrawn)
+    );
+    assert_account_data(proc, 9, dec!(0), dec!(0), dec!(0), false);
+    assert_eq!(
+        proc.account_summary(9).map(AccountSummary::fees),
+        Some(dec!(0))
+    );
+}
+
+#[test]
+fn authorizations() {
+    let proc = &mut TxProc::default();
+    let authorize = |tx, amount| TxRecord::AUTHORIZE(TxRecordMetadata::new(1, tx), amount);
+
+    for record in [
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(10)),
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 2), dec!(2)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(1, 2)),
+    ] {
+        proc.submit_tx_record(record).expect("valid record");
+    }
+
+    assert_eq!(
+        proc.submit_tx_record(authorize(3, dec!(4))),
+        Ok(Applied::Authorized)
+    );
+    assert_eq!(
+        proc.submit_tx_record(authorize(4, dec!(3))),
+        Ok(Applied::Authorized)
+    );
+    assert_eq!(
+        proc.submit_tx_record(authorize(5, dec!(3.5))),
+        Err(TxRejection::InsufficientFunds)
+    );
+    // held by the dispute and the authorizations, told apart by the authorized amount
+    assert_account_data(proc, 1, dec!(3), dec!(9), dec!(12), false);
+    assert_eq!(
+        proc.account_summary(1)
+            .map(AccountSummary::authorized_amount),
+        Some(dec!(7))
+    );
+    assert!(proc.has_authorizations());
+
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CAPTURE(TxRecordMetadata::new(1, 3))),
+        Ok(Applied::Captured)
+    );
+    assert_account_data(proc, 1, dec!(3), dec!(5), dec!(8), false);
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::VOID(TxRecordMetadata::new(1, 4))),
+        Ok(Applied::Voided)
+    );
+    assert_account_data(proc, 1, dec!(6), dec!(2), dec!(8), false);
+    assert!(!proc.has_authorizations());
+
+    for (record, rejection) in [
+        (
+            TxRecord::VOID(TxRecordMetadata::new(1, 3)),
+            TxRejection::AuthorizationClosed,
+        ),
+        (
+            TxRecord::CAPTURE(TxRecordMetadata::new(1, 4)),
+            TxRejection::AuthorizationClosed,
+        ),
+        (
+            TxRecord::CAPTURE(TxRecordMetadata::new(1, 1)),
+            TxRejection::NotAnAuthorization,
+        ),
+        // the declined authorization keeps its transaction id, it can not be settled
+        (
+            TxRecord::CAPTURE(TxRecordMetadata::new(1, 5)),
+            TxRejection::AuthorizationClosed,
+        ),
+        (authorize(5, dec!(1)), TxRejection::DuplicateTransaction),
+        (
+            TxRecord::VOID(TxRecordMetadata::new(1, 6)),
+            TxRejection::UnknownTransaction,
+        ),
+        (
+            TxRecord::VOID(TxRecordMetadata::new(2, 3)),
+            TxRejection::ClientMismatch,
+        ),
+        (
+            TxRecord::DISPUTE(TxRecordMetadata::new(1, 3)),
+            TxRejection::NotDisputable,
+        ),
+        (
+            TxRecord::DEPOSIT(TxRecordMetadata::new(1, 4), dec!(1)),
+            TxRejection::DuplicateTransaction,
+        ),
+    ] {
+        assert_eq!(proc.submit_tx_record(record), Err(rejection));
+    }
+}
+
+#[test]
+fn settle_authorizations_on_locked_account() {
+    let proc = &mut TxProc::default();
+
+    for record in [
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(10)),
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 2), dec!(2)),
+        TxRecord::AUTHORIZE(TxRecordMetadata::new(1, 3), dec!(3)),
+        TxRecord::AUTHORIZE(TxRecordMetadata::new(1, 4), dec!(2)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(1, 2)),
+        TxRecord::CHARGEBACK(TxRecordMetadata::new(1, 2)),
+    ] {
+        proc.submit_tx_record(record).expect("valid record");
+    }
+    assert_account_data(proc, 1, dec!(5), dec!(5), dec!(10), true);
+
+    // the pending authorizations are still settled
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CAPTURE(TxRecordMetadata::new(1, 3))),
+        Ok(Applied::Captured)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::VOID(TxRecordMetadata::new(1, 4))),
+        Ok(Applied::Voided)
+    );
+    assert_account_data(proc, 1, dec!(7), dec!(0), dec!(7), true);
+
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::AUTHORIZE(TxRecordMetadata::new(1, 5), dec!(1))),
+        Err(TxRejection::AccountLocked)
+    );
+}
+
+#[test]
+fn capture_fee() {
+    let proc = &mut TxProc::with_config(TxProcConfig {
+        fees: FeeSchedule {
+            house_client: 9,
+            withdrawal_flat: dec!(1),
+            ..FeeSchedule::default()
+        },
+        ..TxProcConfig::default()
+    });
+
+    for record in [
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(5)),
+        TxRecord::AUTHORIZE(TxRecordMetadata::new(1, 2), dec!(4)),
+        TxRecord::AUTHORIZE(TxRecordMetadata::new(1, 3), dec!(0)),
+    ] {
+        proc.submit_tx_record(record).expect("valid record");
+    }
+    // the authorizations and the void are free, the capture is charged like a withdrawal
+    assert_account_data(proc, 1, dec!(1), dec!(4), dec!(5), false);
+
+    // the available amount covers the authorized amount but not the fee of its capture
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::AUTHORIZE(TxRecordMetadata::new(1, 4), dec!(0.5))),
+        Err(TxRejection::InsufficientFunds)
+    );
+
+    // the available amount is spent in the meantime
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 5), dec!(0))),
+        Ok(Applied::Withdrawn)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::VOID(TxRecordMetadata::new(1, 3))),
+        Ok(Applied::Voided)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CAPTURE(TxRecordMetadata::new(1, 2))),
+        Ok(Applied::Captured)
+    );
+    assert_account_data(proc, 1, dec!(-1), dec!(0), dec!(-1), false);
+    assert_account_data(proc, 9, dec!(2), dec!(0), dec!(2), false);
+
+    // the house account is not charged
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::AUTHORIZE(TxRecordMetadata::new(9, 6), dec!(2))),
+        Ok(Applied::Authorized)
+    );
+}
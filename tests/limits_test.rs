@@ -0,0 +1,74 @@
+use rust_decimal_macros::dec;
+use tx_proc::csv::AccountSummaryWriter;
+use tx_proc::limits::{read_credit_limits, CreditLimit};
+use tx_proc::*;
+
+#[test]
+fn read_limits() {
+    let input = "client, limit, currency\n\
+                 1, 100,\n\
+                 2, 50.5, eur\n";
+
+    let limits = read_credit_limits(input.as_bytes()).expect("valid limits");
+
+    assert_eq!(
+        limits,
+        [
+            CreditLimit {
+                client: 1,
+                currency: Currency::default(),
+                limit: dec!(100),
+            },
+            CreditLimit {
+                client: 2,
+                currency: Currency::new("EUR").expect("valid currency"),
+                limit: dec!(50.5),
+            },
+        ]
+    );
+
+    // the currency column is optional
+    let limits = read_credit_limits("client,limit\n1,100\n".as_bytes()).expect("valid limits");
+    assert_eq!(limits[0].currency, Currency::default());
+
+    let error =
+        read_credit_limits("client,limit\n1,100\n2,-1\n".as_bytes()).expect_err("negative limit");
+    assert_eq!(error.line(), Some(3));
+    assert_eq!(
+        error.to_string(),
+        "invalid credit limit on line 3: negative limit for client 2"
+    );
+
+    let error = read_credit_limits("client,limit\n1,e\n".as_bytes()).expect_err("invalid limit");
+    assert_eq!(error.line(), Some(2));
+}
+
+#[test]
+fn write_limits() {
+    let proc = &mut TxProc::default();
+    proc.set_credit_limits([CreditLimit {
+        client: 1,
+        currency: Currency::default(),
+        limit: dec!(100),
+    }]);
+
+    proc.submit_tx_record(TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 1), dec!(30)))
+        .expect("valid withdrawal");
+    proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(2, 2), dec!(1)))
+        .expect("valid deposit");
+
+    let mut output = Vec::new();
+    let mut writer = AccountSummaryWriter::new(&mut output).with_credit_limit();
+    for (client_id, summary) in proc.summary_iterator() {
+        writer.write(*client_id, summary).expect("valid summary");
+    }
+    writer.flush().expect("flush to memory");
+    drop(writer);
+
+    assert_eq!(
+        String::from_utf8(output).expect("valid UTF-8"),
+        "client,available,held,total,locked,credit_limit,headroom\n\
+         1,-30,0,-30,false,100,70\n\
+         2,1,0,1,false,0,1\n"
+    );
+}
@@ -1,15 +1,41 @@
 use assert_cmd::prelude::*;
-use itertools::Itertools;
 use predicates::prelude::*;
+use std::path::PathBuf;
 use std::process::Command;
 
+// the process id keeps concurrent runs of the tests apart
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("tx_proc_{}_{name}", std::process::id()))
+}
+
 #[test]
 fn no_arg_specified() {
-    let mut cmd = Command::cargo_bin("tx_proc").expect("could not build main binary");
+    let mut cmd = assert_cmd::Command::cargo_bin("tx_proc").expect("could not build main binary");
 
-    cmd.assert()
-        .failure()
-        .stderr(predicate::str::contains("invalid argument count"));
+    // reads from stdin
+    cmd.write_stdin("type, client, tx, amount\ndeposit, 1, 1, 1.5\n")
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,1.5,0,1.5,false\n");
+}
+
+#[test]
+fn stdin_and_multiple_files() {
+    let mut cmd = assert_cmd::Command::cargo_bin("tx_proc").expect("could not build main binary");
+
+    // the withdrawal of client 2 is accepted thanks to the deposit of the first file
+    cmd.arg("tests/data/test_case_from_instructions.csv")
+        .arg("-")
+        .arg("tests/data/test_case_from_instructions.csv")
+        .write_stdin(
+            "type, client, tx, amount\n\
+             deposit, 2, 6, 1.0\n\
+             withdrawal, 2, 7, 3.0\n",
+        )
+        .assert()
+        .code(65)
+        // the last file only repeats transactions
+        .stdout("client,available,held,total,locked\n1,1.5,0,1.5,false\n2,0,0,0,false\n");
 }
 
 #[test]
@@ -18,8 +44,10 @@ fn can_not_open_file() {
 
     cmd.arg("invalid_file.csv")
         .assert()
-        .failure()
-        .stderr(predicate::str::contains("failed to open file"));
+        .code(74)
+        .stderr(predicate::str::contains(
+            "failed to open file invalid_file.csv",
+        ));
 }
 
 #[test]
@@ -27,24 +55,792 @@ fn tests_from_data_dir() {
     let test_cases = [
         (
             "tests/data/test_case_from_instructions.csv",
-            "client,available,held,total,locked\n2,2,0,2,false\n1,1.5,0,1.5,false\n",
+            "client,available,held,total,locked\n1,1.5,0,1.5,false\n2,2,0,2,false\n",
         ),
         ("tests/data/no_headers.csv", ""),
         (
             "tests/data/invalid_records.csv",
-            "client,available,held,total,locked\n2,0,0,0,false\n1,1.0000,1.0005,2.0005,false\n",
+            "client,available,held,total,locked\n1,1.0000,1.0005,2.0005,false\n2,0,0,0,false\n",
         ),
     ];
 
+    let tx_log_path = temp_path("main_test.log");
+
+    // transactions kept in memory, then in an append-only log file
     for (file, expected_stdout) in test_cases {
+        for tx_log in [false, true] {
+            let mut cmd = Command::cargo_bin("tx_proc").expect("could not build main binary");
+
+            cmd.arg(file);
+            if tx_log {
+                cmd.arg("--tx-log").arg(&tx_log_path);
+            }
+
+            // every case has skipped records: declined withdrawal, invalid records, ..
+            cmd.assert().code(65).stdout(expected_stdout);
+        }
+    }
+}
+
+#[test]
+fn rejects_file() {
+    let test_cases = [
+        (
+            "csv",
+            "input,line,record,reason,message\n",
+            "tests/data/invalid_records.csv,22,\"deposit, 2, 5, -3.0\",negative_amount,amount is negative\n",
+        ),
+        (
+            "jsonl",
+            "{\"input\":\"tests/data/invalid_records.csv\",\"line\":4,\"record\":\"invalid_type, 1, 1, 1.0\",\"reason\":\"unknown_type\"",
+            "{\"input\":\"tests/data/invalid_records.csv\",\"line\":25,\"record\":\"withdrawal, 1, 4, -1.5\",\"reason\":\"negative_amount\",\"message\":\"amount is negative\"}\n",
+        ),
+    ];
+
+    for (format, expected_1, expected_2) in test_cases {
+        let rejects_path = temp_path(&format!("rejects_test.{format}"));
+
         let mut cmd = Command::cargo_bin("tx_proc").expect("could not build main binary");
 
-        cmd.arg(file)
+        cmd.arg("tests/data/invalid_records.csv")
+            .arg("--rejects")
+            .arg(&rejects_path)
+            .arg("--rejects-format")
+            .arg(format)
+            .assert()
+            .code(65);
+
+        let rejects = std::fs::read_to_string(&rejects_path).expect("rejects file not written");
+        assert!(rejects.contains(expected_1), "{rejects}");
+        assert!(rejects.contains(expected_2), "{rejects}");
+        // one line per skipped record, plus the CSV header
+        assert_eq!(
+            rejects.lines().count(),
+            if format == "csv" { 23 } else { 22 }
+        );
+    }
+}
+
+#[test]
+fn rejects_input_column() {
+    // each skipped record names its input, with or without workers
+    for workers in ["1", "2"] {
+        let rejects_path = temp_path(&format!("rejects_input_test_{workers}.csv"));
+
+        assert_cmd::Command::cargo_bin("tx_proc")
+            .expect("could not build main binary")
+            .arg("tests/data/test_case_from_instructions.csv")
+            .arg("-")
+            .arg("--workers")
+            .arg(workers)
+            .arg("--rejects")
+            .arg(&rejects_path)
+            .write_stdin("type,client,tx,amount\ndeposit,1,1,1.0\n")
+            .assert()
+            .code(65);
+
+        let rejects = std::fs::read_to_string(&rejects_path).expect("rejects file not written");
+        assert_eq!(
+            rejects,
+            "input,line,record,reason,message\n\
+             tests/data/test_case_from_instructions.csv,6,\"withdrawal, 2, 5, 3.0\",insufficient_funds,\
+             insufficient available funds\n\
+             stdin,2,\"deposit,1,1,1.0\",duplicate_transaction,\
+             transaction has already been processed\n",
+        );
+    }
+}
+
+#[test]
+fn state_in_and_out() {
+    let state_1 = temp_path("main_test_state_1.jsonl");
+    let state_2 = temp_path("main_test_state_2.jsonl");
+
+    // first batch
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/invalid_records.csv")
+        .arg("--state-out")
+        .arg(&state_1)
+        .assert()
+        .code(65);
+
+    // second batch starts from the state of the first one
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/test_case_from_instructions.csv")
+        .arg("--state-in")
+        .arg(&state_1)
+        .arg("--state-out")
+        .arg(&state_2)
+        .assert()
+        .code(65)
+        .stdout(
+            "client,available,held,total,locked\n1,1.5000,1.0005,2.5005,false\n2,0,0,0,false\n",
+        );
+
+    let state = std::fs::read_to_string(&state_2).expect("state file not written");
+    assert!(state.starts_with("{\"header\":{\"version\":1}}\n"));
+    // 2 accounts and 5 transactions, including the declined withdrawal
+    assert_eq!(state.lines().count(), 8);
+}
+
+#[test]
+fn journal_file() {
+    let journal_path = temp_path("main_test_journal.jsonl");
+
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/test_case_from_instructions.csv")
+        .arg("--journal")
+        .arg(&journal_path)
+        .assert()
+        .code(65);
+
+    let journal = std::fs::read_to_string(&journal_path).expect("journal file not written");
+
+    // 2 opened accounts, 3 deposits, 1 withdrawal, the second withdrawal is declined
+    assert_eq!(journal.lines().count(), 7);
+    assert!(journal.contains(
+        "{\"event\":\"funds_withdrawn\",\"client\":1,\"tx\":4,\"amount\":\"1.5\",\
+         \"before\":{\"available\":\"3\",\"held\":\"0\"},\
+         \"after\":{\"available\":\"1.5\",\"held\":\"0\"}}"
+    ));
+}
+
+#[test]
+fn workers() {
+    let sequential = Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/invalid_records.csv")
+        .output()
+        .expect("failed to run main binary");
+
+    let sharded = Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/invalid_records.csv")
+        .arg("--workers")
+        .arg("4")
+        .output()
+        .expect("failed to run main binary");
+
+    assert_eq!(sharded.status.code(), Some(65));
+
+    // same summaries, in the same order
+    assert_eq!(sequential.stdout, sharded.stdout);
+}
+
+#[test]
+fn json_formats() {
+    // JSON lines input and output
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/test_case_from_instructions.jsonl")
+        .arg("--input-format")
+        .arg("jsonl")
+        .arg("--output-format")
+        .arg("jsonl")
+        .assert()
+        .code(65)
+        .stdout("{\"client\":1,\"available\":\"1.5\",\"held\":\"0\",\"total\":\"1.5\",\"locked\":false}\n\
+                 {\"client\":2,\"available\":\"2.0\",\"held\":\"0\",\"total\":\"2.0\",\"locked\":false}\n");
+
+    // descending client id order
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/test_case_from_instructions.jsonl")
+        .arg("--input-format")
+        .arg("jsonl")
+        .arg("--order")
+        .arg("client-desc")
+        .assert()
+        .code(65)
+        .stdout("client,available,held,total,locked\n2,2.0,0,2.0,false\n1,1.5,0,1.5,false\n");
+
+    // CSV input, a single JSON array as output
+    let output = Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/test_case_from_instructions.csv")
+        .arg("--output-format")
+        .arg("json")
+        .output()
+        .expect("failed to run main binary");
+
+    assert_eq!(output.status.code(), Some(65));
+    let stdout = String::from_utf8(output.stdout).expect("valid UTF-8");
+    assert!(
+        stdout.starts_with('[') && stdout.ends_with("]\n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains(
+        "{\"client\":2,\"available\":\"2\",\"held\":\"0\",\"total\":\"2\",\"locked\":false}"
+    ));
+
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/test_case_from_instructions.csv")
+        .arg("--output-format")
+        .arg("xml")
+        .assert()
+        .code(64)
+        .stderr(predicate::str::contains(
+            "invalid value 'xml' for '--output-format",
+        ));
+}
+
+#[test]
+fn help_and_usage_errors() {
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Exit codes:"))
+        .stdout(predicate::str::contains("validate"));
+
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--workers")
+        .arg("two")
+        .assert()
+        .code(64);
+
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/test_case_from_instructions.csv")
+        .arg("--workers")
+        .arg("2")
+        .arg("--journal")
+        .arg(temp_path("main_test_usage_journal.jsonl"))
+        .assert()
+        .code(64)
+        .stderr(predicate::str::contains(
+            "--journal is not supported with --workers",
+        ));
+}
+
+#[test]
+fn validate_and_report() {
+    let state = temp_path("main_test_report_state.jsonl");
+    let rejects = temp_path("main_test_validate_rejects.csv");
+
+    // no output, the skipped records are reported
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("validate")
+        .arg("tests/data/invalid_records.csv")
+        .arg("--rejects")
+        .arg(&rejects)
+        .assert()
+        .code(65)
+        .stdout("");
+    let rejects = std::fs::read_to_string(&rejects).expect("rejects file not written");
+    assert_eq!(rejects.lines().count(), 23);
+
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("process")
+        .arg("tests/data/invalid_records.csv")
+        .arg("--state-out")
+        .arg(&state)
+        .assert()
+        .code(65);
+
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("report")
+        .arg(&state)
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked\n1,1.0000,1.0005,2.0005,false\n2,0,0,0,false\n",
+        );
+
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("report")
+        .arg("tests/data/invalid_records.csv")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("failed to load state"));
+}
+
+#[test]
+fn strict() {
+    let rejects = temp_path("main_test_strict_rejects.csv");
+
+    // stops at the first malformed line, no partial output
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/invalid_records.csv")
+        .arg("--strict")
+        .arg("--rejects")
+        .arg(&rejects)
+        .assert()
+        .code(65)
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "strict mode: stopped at tests/data/invalid_records.csv line 2, malformed_line",
+        ));
+
+    let rejects = std::fs::read_to_string(&rejects).expect("rejects file not written");
+    assert_eq!(rejects.lines().count(), 2);
+
+    // the rejection of the declined withdrawal stops the processing
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("validate")
+        .arg("--strict")
+        .write_stdin("type, client, tx, amount\ndeposit, 1, 1, 1.0\nwithdrawal, 1, 2, 3.0\n")
+        .assert()
+        .code(65)
+        .stderr(predicate::str::contains(
+            "stopped at stdin line 3, insufficient_funds",
+        ));
+
+    // valid input
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--strict")
+        .write_stdin("type, client, tx, amount\ndeposit, 1, 1, 1.0\n")
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,1,0,1,false\n");
+
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/test_case_from_instructions.csv")
+        .arg("--strict")
+        .arg("--workers")
+        .arg("2")
+        .assert()
+        .code(64);
+}
+
+#[test]
+fn csv_layout() {
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/no_headers.csv")
+        .arg("--no-headers")
+        .assert()
+        .code(65)
+        .stdout("client,available,held,total,locked\n1,1.5,0,1.5,false\n2,2,0,2,false\n");
+
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--columns")
+        .arg("tx,client,type,amount")
+        .arg("--delimiter")
+        .arg(";")
+        .write_stdin("id;customer;kind;value\n1;1;deposit;1.5\n2;1;withdrawal;0.5\n")
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,1.0,0,1.0,false\n");
+
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/no_headers.csv")
+        .arg("--columns")
+        .arg("type,client,amount")
+        .assert()
+        .code(64)
+        .stderr(predicate::str::contains(
+            "invalid --columns: missing column: tx",
+        ));
+
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/no_headers.csv")
+        .arg("--delimiter")
+        .arg(";;")
+        .assert()
+        .code(64);
+}
+
+#[test]
+fn decimal_scale() {
+    // 1.00005 has more than 4 places
+    let input = "type, client, tx, amount\n\
+                 deposit, 1, 1, 1.5\n\
+                 deposit, 1, 2, 1.00005\n";
+
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--input-scale")
+        .arg("4")
+        .arg("--output-scale")
+        .arg("4")
+        .write_stdin(input)
+        .assert()
+        .code(65)
+        .stdout("client,available,held,total,locked\n1,1.5000,0.0000,1.5000,false\n")
+        .stderr(predicate::str::contains(
+            "amount with more than 4 decimal places on line 3",
+        ));
+
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--input-scale")
+        .arg("4")
+        .arg("--input-rounding")
+        .arg("up")
+        .arg("--output-scale")
+        .arg("2")
+        .arg("--output-rounding")
+        .arg("down")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,2.50,0.00,2.50,false\n");
+
+    for args in [
+        ["--input-rounding", "up"].as_slice(),
+        ["--output-scale", "29"].as_slice(),
+        ["--output-scale", "2", "--output-rounding", "nearest"].as_slice(),
+    ] {
+        Command::cargo_bin("tx_proc")
+            .expect("could not build main binary")
+            .arg("tests/data/test_case_from_instructions.csv")
+            .args(args)
+            .assert()
+            .code(64);
+    }
+}
+
+#[test]
+fn currencies() {
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--currency-column")
+        .write_stdin(
+            "type, client, tx, amount, currency\n\
+             deposit, 1, 1, 1.5, EUR\n\
+             deposit, 1, 2, 2, USD\n\
+             deposit, 1, 3, 1,\n\
+             withdrawal, 1, 4, 2, EUR\n\
+             dispute, 1, 2,,\n",
+        )
+        .assert()
+        .code(65)
+        .stdout(
+            "client,currency,available,held,total,locked\n\
+             1,,1,0,1,false\n\
+             1,EUR,1.5,0,1.5,false\n\
+             1,USD,0,2,2,false\n",
+        )
+        .stderr(predicate::str::contains("insufficient available funds"));
+
+    // the currency column follows the flag, not the data
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--currency-column")
+        .write_stdin("type, client, tx, amount\ndeposit, 1, 1, 1\n")
+        .assert()
+        .success()
+        .stdout("client,currency,available,held,total,locked\n1,,1,0,1,false\n");
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .write_stdin("type, client, tx, amount, currency\ndeposit, 1, 1, 1, EUR\n")
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,1,0,1,false\n")
+        .stderr(predicate::str::contains(
+            "written without --currency-column",
+        ));
+}
+
+#[test]
+fn credit_limits() {
+    let limits = temp_path("main_test_credit_limits.csv");
+    std::fs::write(&limits, "client,limit,currency\n1,2,\n").expect("failed to write limits");
+
+    // the limit and headroom columns are only written with --credit-limits
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--credit-limits")
+        .arg(&limits)
+        .arg("--workers")
+        .arg("2")
+        .write_stdin(
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 1\n\
+             withdrawal, 1, 2, 2.5\n\
+             withdrawal, 1, 3, 1\n\
+             withdrawal, 2, 4, 1\n",
+        )
+        .assert()
+        .code(65)
+        .stdout(
+            "client,available,held,total,locked,credit_limit,headroom\n\
+             1,-1.5,0,-1.5,false,2,0.5\n\
+             2,0,0,0,false,0,0\n",
+        );
+
+    Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("tests/data/test_case_from_instructions.csv")
+        .arg("--credit-limits")
+        .arg("tests/data/test_case_from_instructions.csv")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("invalid credit limit on line 2"));
+}
+
+#[test]
+fn deposit_dispute_policy() {
+    const INPUT: &str = "type, client, tx, amount\n\
+                         deposit, 1, 1, 5\n\
+                         withdrawal, 1, 2, 3\n\
+                         dispute, 1, 1,\n";
+
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--negative-flag")
+        .write_stdin(INPUT)
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked,shortfall,negative\n\
+             1,-3,5,2,false,0,true\n",
+        );
+
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--deposit-dispute-policy")
+        .arg("reject")
+        .write_stdin(INPUT)
+        .assert()
+        .code(65)
+        .stdout("client,available,held,total,locked\n1,2,0,2,false\n");
+
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--deposit-dispute-policy")
+        .arg("hold-available")
+        .arg("--negative-flag")
+        .arg("--output-format")
+        .arg("jsonl")
+        .write_stdin(INPUT)
+        .assert()
+        .success()
+        .stdout(
+            "{\"client\":1,\"available\":\"0\",\"held\":\"2\",\"total\":\"2\",\"locked\":false,\"shortfall\":\"3\",\"negative\":true}\n",
+        );
+}
+
+#[test]
+fn transfers() {
+    // the same output with clients on different shards
+    for workers in ["1", "2"] {
+        assert_cmd::Command::cargo_bin("tx_proc")
+            .expect("could not build main binary")
+            .arg("--workers")
+            .arg(workers)
+            .write_stdin(
+                "type, client, tx, amount, destination\n\
+                 deposit, 1, 1, 5,\n\
+                 transfer, 1, 2, 3, 2\n\
+                 transfer, 1, 3, 3, 2\n\
+                 transfer, 2, 4, 1,\n\
+                 dispute, 2, 2,,\n\
+                 chargeback, 2, 2,,\n",
+            )
             .assert()
-            .success()
-            .stdout(predicate::function(|stdout_str: &str| {
-                predicate::eq(expected_stdout.split('\n').sorted().as_slice())
-                    .eval(stdout_str.split('\n').sorted().as_slice())
-            }));
+            .code(65)
+            .stdout(
+                "client,available,held,total,locked\n\
+                 1,5,0,5,false\n\
+                 2,0,0,0,true\n",
+            )
+            .stderr(predicate::str::contains("insufficient available funds"))
+            .stderr(predicate::str::contains("missing destination on line 5"));
+    }
+}
+
+#[test]
+fn admin_input() {
+    let admin = temp_path("main_test_admin_input.csv");
+    std::fs::write(
+        &admin,
+        "type,client,tx,amount,reason\nunlock,1,10,,ticket 42\n",
+    )
+    .expect("failed to write admin input");
+    let state = temp_path("main_test_admin_input.state");
+
+    // client 1 is locked by a chargeback
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--state-out")
+        .arg(&state)
+        .write_stdin(
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 3\n\
+             deposit, 1, 2, 2\n\
+             dispute, 1, 1,\n\
+             chargeback, 1, 1,\n",
+        )
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,2,0,2,true\n");
+
+    // unlocked by the admin input, processed last, but not by the end-user input
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--state-in")
+        .arg(&state)
+        .arg("--admin-input")
+        .arg(&admin)
+        .write_stdin(
+            "type, client, tx, amount, reason\n\
+             withdrawal, 1, 3, 1,\n\
+             unlock, 1, 4, , by myself\n",
+        )
+        .assert()
+        .code(65)
+        .stdout("client,available,held,total,locked\n1,2,0,2,false\n")
+        .stderr(predicate::str::contains(
+            "admin record not accepted on line 3: unlock",
+        ))
+        .stderr(predicate::str::contains("account is locked"));
+
+    // the chargeback and the unlock in the same run
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("-")
+        .arg("--admin-input")
+        .arg(&admin)
+        .write_stdin(
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 3\n\
+             deposit, 1, 2, 2\n\
+             dispute, 1, 1,\n\
+             chargeback, 1, 1,\n",
+        )
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,2,0,2,false\n");
+}
+
+#[test]
+fn adjustments() {
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--adjustment-columns")
+        .write_stdin(
+            "type, client, tx, amount, reason, bucket\n\
+             deposit, 1, 1, 3,,\n\
+             adjustment, 1, 2, -0.5, incident 7,\n\
+             adjustment, 1, 3, 1, incident 7, held\n\
+             deposit, 2, 4, 1,,\n",
+        )
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked,adjustments,adjusted\n\
+             1,2.5,1,3.5,false,2,0.5\n\
+             2,1,0,1,false,0,0\n",
+        );
+
+    // the same header without adjustment, and no adjustment columns without the flag
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--adjustment-columns")
+        .write_stdin("type, client, tx, amount\ndeposit, 1, 1, 3\n")
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked,adjustments,adjusted\n1,3,0,3,false,0,0\n");
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .write_stdin(
+            "type, client, tx, amount, reason\n\
+             deposit, 1, 1, 3,\n\
+             adjustment, 1, 2, -0.5, incident 7\n",
+        )
+        .assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,2.5,0,2.5,false\n");
+}
+
+#[test]
+fn fees() {
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .args(["--withdrawal-fee", "0.1", "--withdrawal-fee-rate", "0.01"])
+        .args(["--chargeback-fee", "2", "--fee-account", "9"])
+        .write_stdin(
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 10\n\
+             withdrawal, 1, 2, 5\n\
+             withdrawal, 1, 3, 4.8\n\
+             deposit, 2, 4, 1\n\
+             dispute, 2, 4,\n\
+             chargeback, 2, 4,\n",
+        )
+        .assert()
+        .code(65)
+        .stdout(
+            "client,available,held,total,locked,fees\n\
+             1,4.85,0,4.85,false,0.15\n\
+             2,-2,0,-2,true,2\n\
+             9,2.15,0,2.15,false,0\n",
+        );
+
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--dispute-fee=-1")
+        .write_stdin("type, client, tx, amount\n")
+        .assert()
+        .code(64)
+        .stderr(predicate::str::contains("expected a non-negative amount"));
+}
+
+#[test]
+fn authorizations() {
+    assert_cmd::Command::cargo_bin("tx_proc")
+        .expect("could not build main binary")
+        .arg("--authorized-column")
+        .write_stdin(
+            "type, client, tx, amount\n\
+             deposit, 1, 1, 10\n\
+             deposit, 1, 2, 1\n\
+             dispute, 1, 2,\n\
+             authorize, 1, 3, 4\n\
+             authorize, 1, 4, 2\n\
+             capture, 1, 3,\n\
+             authorize, 1, 5, 3\n\
+             void, 1, 5,\n\
+             deposit, 2, 6, 1\n",
+        )
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked,authorized\n\
+             1,4,3,7,false,2\n\
+             2,1,0,1,false,0\n",
+        );
+
+    // the header does not depend on the pending authorizations
+    for (flag, header) in [
+        (None, "client,available,held,total,locked\n"),
+        (
+            Some("--authorized-column"),
+            "client,available,held,total,locked,authorized\n",
+        ),
+    ] {
+        for input in [
+            "type, client, tx, amount\ndeposit, 1, 1, 10\nauthorize, 1, 2, 3\n",
+            "type, client, tx, amount\ndeposit, 1, 1, 10\nauthorize, 1, 2, 3\ncapture, 1, 2,\n",
+        ] {
+            let mut cmd =
+                assert_cmd::Command::cargo_bin("tx_proc").expect("could not build main binary");
+            if let Some(flag) = flag {
+                cmd.arg(flag);
+            }
+            cmd.write_stdin(input)
+                .assert()
+                .success()
+                .stdout(predicate::str::starts_with(header));
+        }
     }
 }
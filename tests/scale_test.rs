@@ -0,0 +1,130 @@
+use rust_decimal_macros::dec;
+use tx_proc::csv::{AccountSummaryWriter, CsvLineErrorKind, TxRecordReader};
+use tx_proc::input::InvalidRecord;
+use tx_proc::json::{AccountSummaryJsonWriter, JsonLineErrorKind, TxRecordJsonReader};
+use tx_proc::scale::{InputScale, OutputScale, Rounding};
+use tx_proc::*;
+
+#[test]
+fn input_scale() {
+    let reject = InputScale {
+        places: 4,
+        rounding: None,
+    };
+    assert_eq!(reject.apply(dec!(1.5)), Some(dec!(1.5)));
+    assert_eq!(reject.apply(dec!(1.0005)), Some(dec!(1.0005)));
+    assert_eq!(reject.apply(dec!(1.00005)), None);
+
+    let round = |rounding, amount| {
+        InputScale {
+            places: 4,
+            rounding: Some(rounding),
+        }
+        .apply(amount)
+    };
+    assert_eq!(round(Rounding::HalfEven, dec!(1.00005)), Some(dec!(1.0000)));
+    assert_eq!(round(Rounding::HalfEven, dec!(1.00015)), Some(dec!(1.0002)));
+    assert_eq!(round(Rounding::HalfUp, dec!(1.00005)), Some(dec!(1.0001)));
+    assert_eq!(round(Rounding::Down, dec!(1.00009)), Some(dec!(1.0000)));
+    assert_eq!(round(Rounding::Up, dec!(1.00001)), Some(dec!(1.0001)));
+}
+
+#[test]
+fn output_scale() {
+    let scale = OutputScale {
+        places: 4,
+        rounding: Rounding::HalfEven,
+    };
+    // padded with zeros
+    assert_eq!(scale.apply(dec!(1.5)).to_string(), "1.5000");
+    assert_eq!(scale.apply(dec!(0)).to_string(), "0.0000");
+    assert_eq!(scale.apply(dec!(2.00005)).to_string(), "2.0000");
+
+    let scale = OutputScale {
+        places: 2,
+        rounding: Rounding::HalfUp,
+    };
+    assert_eq!(scale.apply(dec!(-1.005)).to_string(), "-1.01");
+}
+
+#[test]
+fn read_records_with_scale() {
+    let input = "type, client, tx, amount\n\
+                 deposit, 1, 1, 1.0005\n\
+                 deposit, 1, 2, 1.00005\n\
+                 dispute, 1, 1, 1.00005\n";
+
+    let scale = InputScale {
+        places: 4,
+        rounding: None,
+    };
+    let records: Vec<_> = TxRecordReader::new(input.as_bytes())
+        .with_scale(scale)
+        .collect();
+
+    assert!(matches!(&records[0], Ok(TxRecord::DEPOSIT(_, amount)) if *amount == dec!(1.0005)));
+    let error = records[1].as_ref().expect_err("expected an error");
+    assert!(matches!(
+        error.kind(),
+        CsvLineErrorKind::TooManyDecimalPlaces(4)
+    ));
+    assert_eq!(error.code(), "too_many_decimal_places");
+    assert_eq!(error.line(), Some(3));
+    // the discarded amount is not checked
+    assert!(matches!(&records[2], Ok(TxRecord::DISPUTE(_))));
+
+    let input = "{\"type\": \"deposit\", \"client\": 1, \"tx\": 2, \"amount\": \"1.00005\"}\n";
+    let mut reader = TxRecordJsonReader::new(input.as_bytes()).with_scale(scale);
+    let error = reader
+        .next()
+        .expect("expected a record")
+        .expect_err("expected an error");
+    assert!(matches!(
+        error.kind(),
+        JsonLineErrorKind::Invalid(InvalidRecord::TooManyDecimalPlaces(4))
+    ));
+
+    let mut reader = TxRecordJsonReader::new(input.as_bytes()).with_scale(InputScale {
+        rounding: Some(Rounding::HalfUp),
+        ..scale
+    });
+    assert!(matches!(
+        reader.next(),
+        Some(Ok(TxRecord::DEPOSIT(_, amount))) if amount == dec!(1.0001)
+    ));
+}
+
+#[test]
+fn write_summaries_with_scale() {
+    let proc = &mut TxProc::default();
+
+    proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(1.5)))
+        .expect("valid deposit");
+    let summary = proc.account_summary(1).expect("account opened");
+
+    let scale = OutputScale {
+        places: 4,
+        rounding: Rounding::HalfEven,
+    };
+
+    let mut output = Vec::new();
+    let mut writer = AccountSummaryWriter::new(&mut output).with_scale(scale);
+    writer.write(1, summary).expect("valid summary");
+    writer.flush().expect("flush to memory");
+    drop(writer);
+
+    assert_eq!(
+        String::from_utf8(output).expect("valid UTF-8"),
+        "client,available,held,total,locked\n1,1.5000,0.0000,1.5000,false\n"
+    );
+
+    let mut output = Vec::new();
+    let mut writer = AccountSummaryJsonWriter::lines(&mut output).with_scale(scale);
+    writer.write(1, summary).expect("valid summary");
+    writer.finish().expect("flush to memory");
+
+    assert_eq!(
+        String::from_utf8(output).expect("valid UTF-8"),
+        "{\"client\":1,\"available\":\"1.5000\",\"held\":\"0.0000\",\"total\":\"1.5000\",\"locked\":false}\n"
+    );
+}
@@ -0,0 +1,108 @@
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+use tx_proc::server::{self, SummaryColumns};
+use tx_proc::TxProc;
+
+fn start_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to listen");
+    let address = listener.local_addr().expect("failed to get local address");
+    thread::spawn(move || server::serve(listener, TxProc::default(), SummaryColumns::default()));
+    address
+}
+
+fn request(address: SocketAddr, request: &str) -> String {
+    let mut stream = TcpStream::connect(address).expect("failed to connect");
+    stream
+        .write_all(request.as_bytes())
+        .expect("failed to send request");
+    stream
+        .shutdown(Shutdown::Write)
+        .expect("failed to shutdown");
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .expect("failed to read response");
+    response
+}
+
+#[test]
+fn concurrent_submissions() {
+    let address = start_server();
+
+    let clients: Vec<_> = (1..=8u16)
+        .map(|client| {
+            thread::spawn(move || {
+                let mut csv = String::from("SUBMIT\ntype, client, tx, amount\n");
+                for i in 0..10u32 {
+                    let tx = client as u32 * 100 + i;
+                    csv.push_str(&format!("deposit, {}, {}, 1.5\n", client, tx));
+                }
+                // insufficient funds
+                csv.push_str(&format!(
+                    "withdrawal, {}, {}, 100\n",
+                    client,
+                    client as u32 * 100 + 50
+                ));
+                csv.push_str("withdrawal, not a record\n");
+                request(address, &csv)
+            })
+        })
+        .collect();
+
+    for client in clients {
+        assert_eq!(
+            client.join().expect("client panicked"),
+            "accepted,rejected,malformed\n10,1,1\n"
+        );
+    }
+
+    let summary = request(address, "SUMMARY\n");
+    let mut lines: Vec<&str> = summary.lines().collect();
+    assert_eq!(lines.remove(0), "client,available,held,total,locked");
+    lines.sort();
+    let expected: Vec<String> = (1..=8)
+        .map(|client| format!("{},15.0,0,15.0,false", client))
+        .collect();
+    assert_eq!(lines, expected);
+}
+
+#[test]
+fn unknown_command() {
+    let address = start_server();
+
+    assert_eq!(request(address, "HELLO\n"), "unknown command: HELLO\n");
+    // no account yet, so not even the header
+    assert_eq!(request(address, "SUMMARY\n"), "");
+}
+
+#[test]
+fn no_admin_records() {
+    let address = start_server();
+
+    assert_eq!(
+        request(
+            address,
+            "SUBMIT\ntype, client, tx, amount, reason\nunlock, 1, 1, , ticket 42\n"
+        ),
+        "accepted,rejected,malformed\n0,0,1\n"
+    );
+}
+
+#[test]
+fn max_connections() {
+    let address = start_server();
+
+    let idle: Vec<_> = (0..server::MAX_CONNECTIONS)
+        .map(|_| TcpStream::connect(address).expect("failed to connect"))
+        .collect();
+
+    // the request waits for a connection to end
+    let summary = thread::spawn(move || request(address, "SUMMARY\n"));
+    thread::sleep(Duration::from_millis(200));
+    assert!(!summary.is_finished());
+
+    drop(idle);
+    assert_eq!(summary.join().expect("client panicked"), "");
+}
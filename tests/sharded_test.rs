@@ -0,0 +1,282 @@
+use rust_decimal::Decimal;
+use tx_proc::sharded::ShardedTxProc;
+use tx_proc::store::TransactionStore;
+use tx_proc::*;
+
+fn summaries<S: TransactionStore>(proc: &TxProc<S>) -> Vec<String> {
+    let mut summaries: Vec<String> = proc
+        .summary_iterator()
+        .map(|(client_id, summary)| {
+            format!(
+                "{client_id},{},{},{},{}",
+                summary.available_amount(),
+                summary.held_amount(),
+                summary.total_amount(),
+                summary.is_locked()
+            )
+        })
+        .collect();
+    summaries.sort();
+    summaries
+}
+
+// deterministic workload where transaction ids are often reused by other clients,
+// so that duplicates, disputes, transfers and authorizations cross the shards
+fn workload(len: usize) -> Vec<TxRecord> {
+    let mut seed: u64 = 42;
+    let mut next = move |modulo: u64| {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (seed >> 33) % modulo
+    };
+
+    (0..len)
+        .map(|_| {
+            let metadata = TxRecordMetadata::new(next(20) as u16, next(200) as u32);
+            let amount = Decimal::new(next(1000) as i64 - 50, 2);
+            match next(14) {
+                0..=3 => TxRecord::DEPOSIT(metadata, amount),
+                4..=5 => TxRecord::WITHDRAWAL(metadata, amount),
+                6..=7 => TxRecord::DISPUTE(metadata),
+                8 => TxRecord::RESOLVE(metadata),
+                9 => TxRecord::CHARGEBACK(metadata),
+                10 => TxRecord::TRANSFER(metadata, next(20) as u16, amount),
+                11 => TxRecord::AUTHORIZE(metadata, amount),
+                12 => TxRecord::CAPTURE(metadata),
+                _ => TxRecord::VOID(metadata),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn sharded_matches_sequential() {
+    let config = TxProcConfig {
+        withdrawal_dispute_policy: WithdrawalDisputePolicy::HoldReversal,
+        ..TxProcConfig::default()
+    };
+
+    let records = workload(5000);
+
+    let sequential = &mut TxProc::with_config(config);
+    let sequential_rejected: Vec<(usize, TxRejection)> = records
+        .iter()
+        .cloned()
+        .enumerate()
+        .filter_map(|(index, record)| {
+            sequential
+                .submit_tx_record(record)
+                .err()
+                .map(|rejection| (index, rejection))
+        })
+        .collect();
+
+    // the workload exercises every kind of rejection crossing the shards
+    assert!(sequential_rejected
+        .iter()
+        .any(|(_, rejection)| *rejection == TxRejection::DuplicateTransaction));
+    assert!(sequential_rejected
+        .iter()
+        .any(|(_, rejection)| *rejection == TxRejection::ClientMismatch));
+
+    for shard_count in [1, 2, 3, 8] {
+        let mut sharded = ShardedTxProc::new(TxProc::with_config(config), shard_count);
+        for (index, record) in records.iter().cloned().enumerate() {
+            sharded.submit_tx_record(record, index);
+        }
+        let (proc, rejected) = sharded.finish();
+
+        assert_eq!(
+            summaries(sequential),
+            summaries(&proc),
+            "{shard_count} shards"
+        );
+        assert_eq!(sequential_rejected, rejected, "{shard_count} shards");
+    }
+}
+
+#[test]
+fn sharded_keeps_existing_state() {
+    let proc = &mut TxProc::default();
+    proc.submit_tx_record(TxRecord::DEPOSIT(
+        TxRecordMetadata::new(1, 1),
+        Decimal::new(3, 0),
+    ))
+    .expect("valid deposit");
+
+    let mut sharded = ShardedTxProc::new(std::mem::take(proc), 4);
+    // the transaction recorded before sharding is known to every shard
+    sharded.submit_tx_record(
+        TxRecord::DEPOSIT(TxRecordMetadata::new(2, 1), Decimal::new(3, 0)),
+        "duplicate",
+    );
+    sharded.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(1, 1)), "dispute");
+    let (proc, rejected) = sharded.finish();
+
+    assert_eq!(
+        rejected,
+        vec![("duplicate", TxRejection::DuplicateTransaction)]
+    );
+    assert_eq!(summaries(&proc), vec!["1,0,3,3,false", "2,0,0,0,false"]);
+}
+
+#[test]
+fn sharded_transfers() {
+    let deposit = |client, tx| TxRecord::DEPOSIT(TxRecordMetadata::new(client, tx), Decimal::ONE);
+    let transfer = |client, tx, destination| {
+        TxRecord::TRANSFER(TxRecordMetadata::new(client, tx), destination, Decimal::ONE)
+    };
+
+    let mut sharded = ShardedTxProc::new(TxProc::default(), 2);
+    for (record, tag) in [
+        (deposit(1, 1), "deposit"),
+        // from shard 1 to shard 0, opening the destination account
+        (transfer(1, 2, 2), "transfer"),
+        (transfer(1, 3, 4), "insufficient"),
+        (TxRecord::DISPUTE(TxRecordMetadata::new(2, 2)), "dispute"),
+        // the source account is credited back in the other shard
+        (
+            TxRecord::CHARGEBACK(TxRecordMetadata::new(2, 2)),
+            "chargeback",
+        ),
+        (transfer(1, 4, 2), "locked"),
+    ] {
+        sharded.submit_tx_record(record, tag);
+    }
+    let (proc, rejected) = sharded.finish();
+
+    assert_eq!(
+        rejected,
+        vec![
+            ("insufficient", TxRejection::InsufficientFunds),
+            ("locked", TxRejection::DestinationLocked)
+        ]
+    );
+    assert_eq!(summaries(&proc), vec!["1,1,0,1,false", "2,0,0,0,true"]);
+}
+
+#[test]
+fn sharded_fees() {
+    let config = TxProcConfig {
+        fees: FeeSchedule {
+            house_client: 7,
+            withdrawal_flat: Decimal::new(5, 2),
+            withdrawal_rate: Decimal::new(1, 2),
+            dispute: Decimal::new(10, 2),
+            chargeback: Decimal::ONE,
+        },
+        ..TxProcConfig::default()
+    };
+    let fees = |proc: &TxProc| -> Vec<(u16, Decimal)> {
+        proc.summary_iterator()
+            .map(|(client_id, summary)| (*client_id, summary.fees()))
+            .collect()
+    };
+
+    let records = workload(2000);
+
+    let sequential = &mut TxProc::with_config(config);
+    let sequential_rejected: Vec<(usize, TxRejection)> = records
+        .iter()
+        .cloned()
+        .enumerate()
+        .filter_map(|(index, record)| {
+            sequential
+                .submit_tx_record(record)
+                .err()
+                .map(|rejection| (index, rejection))
+        })
+        .collect();
+    assert!(!sequential
+        .account_summary(7)
+        .expect("house account")
+        .available_amount()
+        .is_zero());
+
+    // the house account is moved by the records of every shard
+    for shard_count in [1, 2, 3, 8] {
+        let mut sharded = ShardedTxProc::new(TxProc::with_config(config), shard_count);
+        for (index, record) in records.iter().cloned().enumerate() {
+            sharded.submit_tx_record(record, index);
+        }
+        let (proc, rejected) = sharded.finish();
+
+        assert_eq!(
+            summaries(sequential),
+            summaries(&proc),
+            "{shard_count} shards"
+        );
+        assert_eq!(fees(sequential), fees(&proc), "{shard_count} shards");
+        assert_eq!(sequential_rejected, rejected, "{shard_count} shards");
+    }
+
+    // charged back transfers, with 2 shards: the source on the shard of the destination and the
+    // house account on the other one, then the house account on the shard of the destination
+    let deposit = |client, tx, amount| {
+        TxRecord::DEPOSIT(TxRecordMetadata::new(client, tx), Decimal::new(amount, 0))
+    };
+    let transfer = |client, tx, destination| {
+        TxRecord::TRANSFER(
+            TxRecordMetadata::new(client, tx),
+            destination,
+            Decimal::new(5, 0),
+        )
+    };
+    let chargeback = |client, tx| {
+        [
+            TxRecord::DISPUTE(TxRecordMetadata::new(client, tx)),
+            TxRecord::CHARGEBACK(TxRecordMetadata::new(client, tx)),
+        ]
+    };
+    for (house_client, records) in [
+        (
+            2,
+            [
+                vec![deposit(1, 1, 10), transfer(1, 2, 3)],
+                chargeback(3, 2).to_vec(),
+            ]
+            .concat(),
+        ),
+        (
+            4,
+            [
+                vec![deposit(4, 1, 100), deposit(1, 2, 10), transfer(1, 3, 2)],
+                chargeback(2, 3).to_vec(),
+            ]
+            .concat(),
+        ),
+    ] {
+        let config = TxProcConfig {
+            fees: FeeSchedule {
+                house_client,
+                chargeback: Decimal::ONE,
+                ..FeeSchedule::default()
+            },
+            ..TxProcConfig::default()
+        };
+
+        let sequential = &mut TxProc::with_config(config);
+        for record in records.iter().cloned() {
+            sequential.submit_tx_record(record).expect("valid record");
+        }
+
+        let mut sharded = ShardedTxProc::new(TxProc::with_config(config), 2);
+        for record in records {
+            sharded.submit_tx_record(record, ());
+        }
+        let (proc, rejected) = sharded.finish();
+
+        assert!(rejected.is_empty());
+        assert_eq!(
+            summaries(sequential),
+            summaries(&proc),
+            "house account {house_client}"
+        );
+        assert_eq!(
+            fees(sequential),
+            fees(&proc),
+            "house account {house_client}"
+        );
+    }
+}
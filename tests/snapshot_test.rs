@@ -0,0 +1,411 @@
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use tx_proc::snapshot::SnapshotError;
+use tx_proc::store::{AppendLogStore, InMemoryStore, TransactionStore};
+use tx_proc::*;
+
+fn assert_account_data<S: TransactionStore>(
+    proc: &TxProc<S>,
+    client_id: u16,
+    available: Decimal,
+    held: Decimal,
+    locked: bool,
+) {
+    let summary = proc
+        .account_summary(client_id)
+        .unwrap_or_else(|| panic!("summary for client {client_id} not found"));
+    assert_eq!(summary.available_amount(), available);
+    assert_eq!(summary.held_amount(), held);
+    assert_eq!(summary.is_locked(), locked);
+}
+
+// client 1: deposit 3 (tx 1), deposit 2 (tx 2), withdrawal 1 (tx 3), dispute of tx 1
+// client 2: deposit 1 (tx 4) charged back
+fn processed() -> TxProc {
+    let mut proc = TxProc::default();
+
+    for record in [
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(3)),
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 2), dec!(2)),
+        TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 3), dec!(1)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(1, 1)),
+        TxRecord::DEPOSIT(TxRecordMetadata::new(2, 4), dec!(1)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(2, 4)),
+        TxRecord::CHARGEBACK(TxRecordMetadata::new(2, 4)),
+    ] {
+        proc.submit_tx_record(record).expect("valid record");
+    }
+
+    proc
+}
+
+#[test]
+fn save_and_load() {
+    let mut processed = processed();
+    assert_eq!(
+        processed.submit_tx_record(TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 5), dec!(9))),
+        Err(TxRejection::InsufficientFunds)
+    );
+
+    let mut snapshot = Vec::new();
+    processed.save(&mut snapshot).expect("failed to save");
+    assert!(String::from_utf8_lossy(&snapshot).contains(
+        "{\"transaction\":{\"type\":\"withdrawal\",\"client\":1,\"tx\":5,\"amount\":\"9\",\
+         \"disputed\":false,\"chargedback\":false,\"declined\":true}}"
+    ));
+
+    let proc = &mut TxProc::load(snapshot.as_slice()).expect("failed to load");
+
+    assert_account_data(proc, 1, dec!(1), dec!(3), false);
+    assert_account_data(proc, 2, dec!(0), dec!(0), true);
+    assert_eq!(proc.summary_iterator().count(), 2);
+
+    // transactions are restored, including the declined withdrawal
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 2), dec!(2))),
+        Err(TxRejection::DuplicateTransaction)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 5), dec!(2))),
+        Err(TxRejection::DuplicateTransaction)
+    );
+
+    // flags are restored
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(1, 1))),
+        Err(TxRejection::AlreadyDisputed)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(1, 1))),
+        Ok(Applied::Resolved)
+    );
+    assert_account_data(proc, 1, dec!(4), dec!(0), false);
+}
+
+#[test]
+fn save_strict() {
+    let mut proc = TxProc::with_config(TxProcConfig {
+        strict: true,
+        ..TxProcConfig::default()
+    });
+    proc.submit_tx_record(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(3)))
+        .expect("valid record");
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 2), dec!(9))),
+        Err(TxRejection::InsufficientFunds)
+    );
+
+    let mut snapshot = Vec::new();
+    proc.save(&mut snapshot).expect("failed to save");
+
+    // the declined withdrawal that halted the processing is not saved
+    let lines = String::from_utf8(snapshot.clone()).expect("valid UTF-8");
+    assert!(lines.contains("{\"transaction\":{\"type\":\"deposit\",\"client\":1,\"tx\":1,"));
+    assert!(!lines.contains("\"tx\":2,"));
+
+    let proc = &mut TxProc::load(snapshot.as_slice()).expect("failed to load");
+    assert_account_data(proc, 1, dec!(3), dec!(0), false);
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 2), dec!(1))),
+        Ok(Applied::Withdrawn)
+    );
+}
+
+#[test]
+fn save_and_load_currencies() {
+    let eur = Currency::new("EUR").expect("valid currency");
+
+    let mut proc = processed();
+    proc.submit_tx_record(TxRecord::DEPOSIT(
+        TxRecordMetadata::new(1, 5).with_currency(eur),
+        dec!(2),
+    ))
+    .expect("valid record");
+
+    let mut snapshot = Vec::new();
+    proc.save(&mut snapshot).expect("failed to save");
+
+    // the currency is only written for the other currencies
+    let lines = String::from_utf8(snapshot.clone()).expect("valid UTF-8");
+    assert!(lines.contains("{\"account\":{\"client\":1,\"currency\":\"EUR\","));
+    assert!(lines.contains("{\"account\":{\"client\":2,\"available\""));
+
+    let proc = &mut TxProc::load(snapshot.as_slice()).expect("failed to load");
+
+    assert_account_data(proc, 1, dec!(1), dec!(3), false);
+    assert_eq!(proc.summary_iterator().count(), 3);
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(1, 5))),
+        Ok(Applied::Disputed)
+    );
+    assert_eq!(
+        proc.account_summary_in(1, eur)
+            .expect("eur account")
+            .held_amount(),
+        dec!(2)
+    );
+}
+
+#[test]
+fn save_and_load_transfers() {
+    let mut proc = processed();
+    proc.submit_tx_record(TxRecord::TRANSFER(TxRecordMetadata::new(1, 5), 3, dec!(1)))
+        .expect("valid record");
+
+    let mut snapshot = Vec::new();
+    proc.save(&mut snapshot).expect("failed to save");
+
+    let lines = String::from_utf8(snapshot.clone()).expect("valid UTF-8");
+    assert!(lines.contains(
+        "{\"transaction\":{\"type\":\"transfer\",\"client\":1,\"tx\":5,\"destination\":3,"
+    ));
+
+    let proc = &mut TxProc::load(snapshot.as_slice()).expect("failed to load");
+    assert_account_data(proc, 3, dec!(1), dec!(0), false);
+
+    // the destination can still charge the transfer back
+    for record in [
+        TxRecord::DISPUTE(TxRecordMetadata::new(3, 5)),
+        TxRecord::CHARGEBACK(TxRecordMetadata::new(3, 5)),
+    ] {
+        proc.submit_tx_record(record).expect("valid record");
+    }
+    assert_account_data(proc, 1, dec!(1), dec!(3), false);
+    assert_account_data(proc, 3, dec!(0), dec!(0), true);
+}
+
+#[test]
+fn save_and_load_adjustments() {
+    let mut proc = processed();
+    proc.submit_tx_record(TxRecord::ADJUSTMENT(
+        TxRecordMetadata::new(3, 5),
+        Bucket::Held,
+        dec!(0.5),
+        "incident 7".to_string(),
+    ))
+    .expect("valid record");
+
+    let mut snapshot = Vec::new();
+    proc.save(&mut snapshot).expect("failed to save");
+
+    let lines = String::from_utf8(snapshot.clone()).expect("valid UTF-8");
+    assert!(lines.contains(
+        "{\"account\":{\"client\":3,\"available\":\"0\",\"held\":\"0.5\",\"locked\":false,\"adjustments\":1,\"adjusted_amount\":\"0.5\"}}"
+    ));
+    assert!(lines.contains(
+        "{\"transaction\":{\"type\":\"adjustment\",\"client\":3,\"tx\":5,\"bucket\":\"held\",\"amount\":\"0.5\",\"reason\":\"incident 7\","
+    ));
+
+    let proc = &mut TxProc::load(snapshot.as_slice()).expect("failed to load");
+    assert_account_data(proc, 3, dec!(0), dec!(0.5), false);
+    let summary = proc.account_summary(3).expect("account 3");
+    assert_eq!(summary.adjustments(), 1);
+    assert_eq!(summary.adjusted_amount(), dec!(0.5));
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::DISPUTE(TxRecordMetadata::new(3, 5))),
+        Err(TxRejection::NotDisputable)
+    );
+}
+
+#[test]
+fn save_and_load_authorizations() {
+    let mut proc = processed();
+    for record in [
+        TxRecord::AUTHORIZE(TxRecordMetadata::new(1, 5), dec!(0.5)),
+        TxRecord::AUTHORIZE(TxRecordMetadata::new(1, 6), dec!(0.5)),
+        TxRecord::VOID(TxRecordMetadata::new(1, 6)),
+    ] {
+        proc.submit_tx_record(record).expect("valid record");
+    }
+
+    let mut snapshot = Vec::new();
+    proc.save(&mut snapshot).expect("failed to save");
+
+    let lines = String::from_utf8(snapshot.clone()).expect("valid UTF-8");
+    assert!(lines.contains(
+        "{\"account\":{\"client\":1,\"available\":\"0.5\",\"held\":\"3.5\",\"locked\":false,\"authorized\":\"0.5\"}}"
+    ));
+    assert!(lines.contains(
+        "\"tx\":6,\"amount\":\"0.5\",\"disputed\":false,\"chargedback\":false,\"voided\":true}}"
+    ));
+
+    let proc = &mut TxProc::load(snapshot.as_slice()).expect("failed to load");
+    assert_eq!(
+        proc.account_summary(1)
+            .map(AccountSummary::authorized_amount),
+        Some(dec!(0.5))
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CAPTURE(TxRecordMetadata::new(1, 6))),
+        Err(TxRejection::AuthorizationClosed)
+    );
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CAPTURE(TxRecordMetadata::new(1, 5))),
+        Ok(Applied::Captured)
+    );
+    assert_account_data(proc, 1, dec!(0.5), dec!(3), false);
+}
+
+#[test]
+fn save_and_load_fees() {
+    let mut proc = TxProc::with_config(TxProcConfig {
+        fees: FeeSchedule {
+            house_client: 9,
+            dispute: dec!(0.5),
+            ..FeeSchedule::default()
+        },
+        ..TxProcConfig::default()
+    });
+    for record in [
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(5)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(1, 1)),
+    ] {
+        proc.submit_tx_record(record).expect("valid record");
+    }
+
+    let mut snapshot = Vec::new();
+    proc.save(&mut snapshot).expect("failed to save");
+
+    let lines = String::from_utf8(snapshot.clone()).expect("valid UTF-8");
+    assert!(lines.contains(
+        "{\"account\":{\"client\":1,\"available\":\"-0.5\",\"held\":\"5\",\"locked\":false,\"fees\":\"0.5\"}}"
+    ));
+
+    // the fee schedule is configuration, not part of the snapshot
+    let proc = TxProc::load(snapshot.as_slice()).expect("failed to load");
+    assert_account_data(&proc, 9, dec!(0.5), dec!(0), false);
+    assert_eq!(
+        proc.account_summary(1).map(AccountSummary::fees),
+        Some(dec!(0.5))
+    );
+    assert!(proc.has_fees());
+}
+
+#[test]
+fn save_and_load_shortfall() {
+    let mut proc = TxProc::with_config(TxProcConfig {
+        deposit_dispute_policy: DepositDisputePolicy::HoldAvailable,
+        ..TxProcConfig::default()
+    });
+    for record in [
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(5)),
+        TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 2), dec!(3)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(1, 1)),
+    ] {
+        proc.submit_tx_record(record).expect("valid record");
+    }
+
+    let mut snapshot = Vec::new();
+    proc.save(&mut snapshot).expect("failed to save");
+
+    let proc = &mut TxProc::load(snapshot.as_slice()).expect("failed to load");
+    assert_eq!(
+        proc.account_summary(1).map(|s| s.shortfall()),
+        Some(dec!(3))
+    );
+
+    // the resolve clears the shortfall of the loaded transaction
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(1, 1))),
+        Ok(Applied::Resolved)
+    );
+    assert_account_data(proc, 1, dec!(2), dec!(0), false);
+    assert_eq!(
+        proc.account_summary(1).map(|s| s.shortfall()),
+        Some(dec!(0))
+    );
+}
+
+#[test]
+fn save_and_load_withdrawal_dispute_policy() {
+    let mut proc = TxProc::with_config(TxProcConfig {
+        withdrawal_dispute_policy: WithdrawalDisputePolicy::HoldReversal,
+        ..TxProcConfig::default()
+    });
+    for record in [
+        TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(10)),
+        TxRecord::WITHDRAWAL(TxRecordMetadata::new(1, 2), dec!(6)),
+        TxRecord::DISPUTE(TxRecordMetadata::new(1, 2)),
+    ] {
+        proc.submit_tx_record(record).expect("valid record");
+    }
+
+    let mut snapshot = Vec::new();
+    proc.save(&mut snapshot).expect("failed to save");
+    assert!(String::from_utf8_lossy(&snapshot).contains(
+        "\"tx\":2,\"amount\":\"6\",\"disputed\":true,\"chargedback\":false,\"policy\":\"hold_reversal\"}}"
+    ));
+
+    // the dispute is resolved under the policy it was opened with
+    let config = TxProcConfig {
+        withdrawal_dispute_policy: WithdrawalDisputePolicy::ProvisionalCredit,
+        ..TxProcConfig::default()
+    };
+    let proc = &mut TxProc::load_with_store(config, InMemoryStore::default(), snapshot.as_slice())
+        .expect("failed to load");
+    assert_account_data(proc, 1, dec!(4), dec!(6), false);
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::RESOLVE(TxRecordMetadata::new(1, 2))),
+        Ok(Applied::Resolved)
+    );
+    assert_account_data(proc, 1, dec!(4), dec!(0), false);
+
+    // a disputed withdrawal without its policy is refused
+    assert!(matches!(
+        TxProc::load(
+            "{\"header\":{\"version\":1}}\n\
+             {\"transaction\":{\"type\":\"withdrawal\",\"client\":1,\"tx\":2,\"amount\":\"6\",\
+             \"disputed\":true,\"chargedback\":false}}\n"
+                .as_bytes()
+        ),
+        Err(SnapshotError::Invalid { line: 2, .. })
+    ));
+}
+
+#[test]
+fn load_with_store() {
+    let mut snapshot = Vec::new();
+    processed().save(&mut snapshot).expect("failed to save");
+
+    let store = AppendLogStore::create(
+        std::env::temp_dir().join(format!("tx_proc_snapshot_test_{}.log", std::process::id())),
+    )
+    .expect("failed to create log");
+    let proc = &mut TxProc::load_with_store(TxProcConfig::default(), store, snapshot.as_slice())
+        .expect("failed to load");
+
+    assert_account_data(proc, 1, dec!(1), dec!(3), false);
+    assert_account_data(proc, 2, dec!(0), dec!(0), true);
+
+    assert_eq!(
+        proc.submit_tx_record(TxRecord::CHARGEBACK(TxRecordMetadata::new(1, 1))),
+        Ok(Applied::ChargedBack)
+    );
+    assert_account_data(proc, 1, dec!(1), dec!(0), true);
+}
+
+#[test]
+fn invalid_snapshots() {
+    assert!(matches!(
+        TxProc::load("".as_bytes()),
+        Err(SnapshotError::MissingHeader)
+    ));
+
+    assert!(matches!(
+        TxProc::load(
+            "{\"account\":{\"client\":1,\"available\":\"1\",\"held\":\"0\",\"locked\":false}}\n"
+                .as_bytes()
+        ),
+        Err(SnapshotError::MissingHeader)
+    ));
+
+    assert!(matches!(
+        TxProc::load("{\"header\":{\"version\":0}}\n".as_bytes()),
+        Err(SnapshotError::UnsupportedVersion(0))
+    ));
+
+    assert!(matches!(
+        TxProc::load("{\"header\":{\"version\":1}}\n{\"account\":{}}\n".as_bytes()),
+        Err(SnapshotError::Invalid { line: 2, .. })
+    ));
+}
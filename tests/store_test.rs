@@ -0,0 +1,224 @@
+use rust_decimal_macros::dec;
+use std::fs::File;
+use std::path::PathBuf;
+use tx_proc::csv::TxRecordReader;
+use tx_proc::store::{AppendLogStore, TransactionStore, APPEND_LOG_VERSION};
+use tx_proc::*;
+
+// the process id keeps concurrent runs of the tests apart
+fn tx_log_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "tx_proc_store_test_{}_{name}.log",
+        std::process::id()
+    ))
+}
+
+fn process_file<S: TransactionStore>(file: &str, proc: &mut TxProc<S>) {
+    for record in TxRecordReader::new(File::open(file).expect("failed to open file")).flatten() {
+        let _ = proc.submit_tx_record(record);
+    }
+}
+
+fn summaries<S: TransactionStore>(proc: &TxProc<S>) -> Vec<String> {
+    let mut summaries: Vec<String> = proc
+        .summary_iterator()
+        .map(|(client_id, summary)| {
+            format!(
+                "{client_id},{},{},{},{}",
+                summary.available_amount(),
+                summary.held_amount(),
+                summary.total_amount(),
+                summary.is_locked()
+            )
+        })
+        .collect();
+    summaries.sort();
+    summaries
+}
+
+#[test]
+fn append_log_store_matches_in_memory_store() {
+    for (name, file) in [
+        ("instructions", "tests/data/test_case_from_instructions.csv"),
+        ("no_headers", "tests/data/no_headers.csv"),
+        ("invalid_records", "tests/data/invalid_records.csv"),
+    ] {
+        let in_memory = &mut TxProc::default();
+        process_file(file, in_memory);
+
+        let store = AppendLogStore::create(tx_log_path(name)).expect("failed to create log");
+        let append_log = &mut TxProc::with_store(TxProcConfig::default(), store);
+        process_file(file, append_log);
+
+        assert_eq!(summaries(in_memory), summaries(append_log), "{file}");
+    }
+}
+
+#[test]
+fn append_log_store_reopen() {
+    let path = tx_log_path("reopen");
+
+    let mut store = AppendLogStore::create(&path).expect("failed to create log");
+    assert!(store
+        .insert_if_absent(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(1.5)))
+        .expect("failed to insert"));
+    let eur = Currency::new("EUR").expect("valid currency");
+    assert!(store
+        .insert_if_absent(TxRecord::WITHDRAWAL(
+            TxRecordMetadata::new(2, 2).with_currency(eur),
+            dec!(0.5)
+        ))
+        .expect("failed to insert"));
+    assert!(store
+        .insert_if_absent(TxRecord::TRANSFER(TxRecordMetadata::new(3, 4), 1, dec!(2)))
+        .expect("failed to insert"));
+    assert!(store
+        .insert_if_absent(TxRecord::ADJUSTMENT(
+            TxRecordMetadata::new(3, 5),
+            Bucket::Held,
+            dec!(-1),
+            "incident 7".to_string()
+        ))
+        .expect("failed to insert"));
+    assert!(store
+        .insert_if_absent(TxRecord::AUTHORIZE(TxRecordMetadata::new(3, 6), dec!(1)))
+        .expect("failed to insert"));
+    store
+        .update_flags(1, TxRecordFlags::new(true, false).with_shortfall(dec!(0.5)))
+        .expect("failed to update flags");
+    store
+        .update_flags(6, TxRecordFlags::default().with_captured())
+        .expect("failed to update flags");
+    store
+        .update_flags(2, TxRecordFlags::default().with_declined())
+        .expect("failed to update flags");
+    assert!(store
+        .insert_if_absent(TxRecord::WITHDRAWAL(TxRecordMetadata::new(3, 7), dec!(1)))
+        .expect("failed to insert"));
+    store
+        .update_flags(
+            7,
+            TxRecordFlags::new(true, false)
+                .with_withdrawal_dispute_policy(Some(WithdrawalDisputePolicy::ProvisionalCredit)),
+        )
+        .expect("failed to update flags");
+    drop(store);
+
+    let mut store = AppendLogStore::open(&path).expect("failed to open log");
+
+    // the latest entry of each transaction is indexed
+    assert_eq!(
+        store.get(1).expect("failed to read"),
+        Some((
+            TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(1.5)),
+            TxRecordFlags::new(true, false).with_shortfall(dec!(0.5))
+        ))
+    );
+    assert_eq!(
+        store.get(2).expect("failed to read"),
+        Some((
+            TxRecord::WITHDRAWAL(TxRecordMetadata::new(2, 2).with_currency(eur), dec!(0.5)),
+            TxRecordFlags::default().with_declined()
+        ))
+    );
+    assert_eq!(
+        store.get(4).expect("failed to read"),
+        Some((
+            TxRecord::TRANSFER(TxRecordMetadata::new(3, 4), 1, dec!(2)),
+            TxRecordFlags::default()
+        ))
+    );
+    assert_eq!(
+        store.get(6).expect("failed to read"),
+        Some((
+            TxRecord::AUTHORIZE(TxRecordMetadata::new(3, 6), dec!(1)),
+            TxRecordFlags::default().with_captured()
+        ))
+    );
+    // the disputed withdrawal keeps its dispute policy
+    assert_eq!(
+        store.get(7).expect("failed to read"),
+        Some((
+            TxRecord::WITHDRAWAL(TxRecordMetadata::new(3, 7), dec!(1)),
+            TxRecordFlags::new(true, false)
+                .with_withdrawal_dispute_policy(Some(WithdrawalDisputePolicy::ProvisionalCredit))
+        ))
+    );
+    // the reason of an adjustment is not kept in the log
+    assert_eq!(
+        store.get(5).expect("failed to read"),
+        Some((
+            TxRecord::ADJUSTMENT(
+                TxRecordMetadata::new(3, 5),
+                Bucket::Held,
+                dec!(-1),
+                String::new()
+            ),
+            TxRecordFlags::default()
+        ))
+    );
+    assert_eq!(store.get(3).expect("failed to read"), None);
+
+    // already recorded transaction
+    assert!(!store
+        .insert_if_absent(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 1), dec!(1.5)))
+        .expect("failed to insert"));
+
+    // new entries are appended after the existing ones
+    assert!(store
+        .insert_if_absent(TxRecord::DEPOSIT(TxRecordMetadata::new(1, 3), dec!(3)))
+        .expect("failed to insert"));
+    assert_eq!(
+        store.get(3).expect("failed to read"),
+        Some((
+            TxRecord::DEPOSIT(TxRecordMetadata::new(1, 3), dec!(3)),
+            TxRecordFlags::default()
+        ))
+    );
+    assert_eq!(
+        store
+            .get(1)
+            .expect("failed to read")
+            .map(|(_, flags)| flags),
+        Some(TxRecordFlags::new(true, false).with_shortfall(dec!(0.5)))
+    );
+}
+
+#[test]
+fn append_log_store_header() {
+    // a new file gets the header, then opens like a created one
+    let path = tx_log_path("header");
+    let _ = std::fs::remove_file(&path);
+    drop(AppendLogStore::open(&path).expect("failed to create log"));
+    let log = std::fs::read(&path).expect("log not written");
+    assert_eq!(log.len(), 8);
+    assert_eq!(&log[4..], &APPEND_LOG_VERSION.to_le_bytes());
+    AppendLogStore::open(&path).expect("failed to open log");
+
+    // a log without header, e.g. written before the header was introduced: a single deposit entry
+    let mut entry = vec![0; 45];
+    entry[3] = 1;
+    std::fs::write(&path, &entry).expect("failed to write log");
+    let error = AppendLogStore::open(&path)
+        .err()
+        .expect("expected an error");
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(error.to_string(), "transaction log header is missing");
+
+    // a log written with another version
+    let mut log = log;
+    log[4..].copy_from_slice(&(APPEND_LOG_VERSION + 1).to_le_bytes());
+    std::fs::write(&path, &log).expect("failed to write log");
+    let error = AppendLogStore::open(&path)
+        .err()
+        .expect("expected an error");
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "unsupported transaction log version {}, expected {}",
+            APPEND_LOG_VERSION + 1,
+            APPEND_LOG_VERSION
+        )
+    );
+}